@@ -1,6 +1,9 @@
 #![allow(elided_lifetimes_in_paths)]
 
-use std::io::{self, Read};
+use std::{
+    hash::{DefaultHasher, Hasher},
+    io::{self, Read},
+};
 
 use divan::counter::BytesCount;
 use lockstitch::{Protocol, TAG_LEN};
@@ -16,6 +19,19 @@ fn hash<const LEN: usize>(bencher: divan::Bencher) {
     });
 }
 
+const FIELD_COUNTS: &[usize] = &[1024, 1024 * 1024];
+
+#[divan::bench(consts = FIELD_COUNTS)]
+fn mix_small_fields<const N: usize>(bencher: divan::Bencher) {
+    bencher.counter(BytesCount::new(N * 4)).bench(|| {
+        let mut protocol = Protocol::new("fields");
+        for i in 0..N as u32 {
+            protocol.mix("field", &i.to_le_bytes());
+        }
+        protocol.derive_array::<32>("digest")
+    });
+}
+
 #[divan::bench(consts = LENS)]
 fn hash_writer<const LEN: usize>(bencher: divan::Bencher) {
     bencher
@@ -75,6 +91,62 @@ fn prf<const LEN: usize>(bencher: divan::Bencher) {
     );
 }
 
+const SMALL_LENS: &[usize] = &[16, 32, 64];
+
+#[divan::bench(consts = SMALL_LENS)]
+fn seal<const LEN: usize>(bencher: divan::Bencher) {
+    let key = [0u8; 32];
+    let nonce = [0u8; 16];
+    bencher.with_inputs(|| vec![0u8; LEN + TAG_LEN]).counter(BytesCount::new(LEN)).bench_values(
+        |mut block| {
+            let mut protocol = Protocol::new("seal");
+            protocol.mix("key", &key);
+            protocol.mix("nonce", &nonce);
+            protocol.seal("message", &mut block);
+            block
+        },
+    );
+}
+
+#[divan::bench(consts = SMALL_LENS)]
+fn open<const LEN: usize>(bencher: divan::Bencher) {
+    let key = [0u8; 32];
+    let nonce = [0u8; 16];
+    let mut protocol = Protocol::new("seal");
+    protocol.mix("key", &key);
+    protocol.mix("nonce", &nonce);
+    let mut ciphertext = vec![0u8; LEN + TAG_LEN];
+    protocol.seal("message", &mut ciphertext);
+    bencher.with_inputs(|| ciphertext.clone()).counter(BytesCount::new(LEN)).bench_values(
+        |mut block| {
+            let mut protocol = Protocol::new("seal");
+            protocol.mix("key", &key);
+            protocol.mix("nonce", &nonce);
+            protocol.open("message", &mut block).is_some()
+        },
+    );
+}
+
+const SHORT_LENS: &[usize] = &[8, 16, 32, 64, 256];
+
+#[divan::bench(consts = SHORT_LENS)]
+fn short_hash<const LEN: usize>(bencher: divan::Bencher) {
+    let key = [0u8; 16];
+    bencher
+        .with_inputs(|| vec![0u8; LEN])
+        .counter(BytesCount::new(LEN))
+        .bench_refs(|data| lockstitch::short_hash(&key, data));
+}
+
+#[divan::bench(consts = SHORT_LENS)]
+fn siphash<const LEN: usize>(bencher: divan::Bencher) {
+    bencher.with_inputs(|| vec![0u8; LEN]).counter(BytesCount::new(LEN)).bench_refs(|data| {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(data);
+        hasher.finish()
+    });
+}
+
 #[global_allocator]
 static ALLOC: divan::AllocProfiler = divan::AllocProfiler::system();
 
@@ -0,0 +1,66 @@
+//! Times the core operations, which are available with and without the `std` feature, and prints
+//! one `name nanoseconds` line per operation for `cargo xtask bench-features`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use lockstitch::{Protocol, TAG_LEN};
+
+const LEN: usize = 16 * 1024;
+const SAMPLES: usize = 50;
+const SAMPLE_TIME: Duration = Duration::from_millis(10);
+
+/// Returns the fastest mean time per iteration of `f` across all samples, in nanoseconds.
+fn time(mut f: impl FnMut()) -> f64 {
+    let mut iters = 1u32;
+    loop {
+        let start = Instant::now();
+        for _ in 0..iters {
+            f();
+        }
+        if start.elapsed() >= SAMPLE_TIME {
+            break;
+        }
+        iters *= 2;
+    }
+
+    (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                f();
+            }
+            start.elapsed().as_nanos() as f64 / f64::from(iters)
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn main() {
+    let message = vec![0u8; LEN];
+    let mut block = vec![0u8; LEN + TAG_LEN];
+
+    let mix = time(|| {
+        let mut protocol = Protocol::new("mix");
+        protocol.mix("message", black_box(&message));
+        black_box(protocol);
+    });
+
+    let derive = time(|| {
+        let mut protocol = Protocol::new("derive");
+        let mut out = [0u8; LEN];
+        protocol.derive("output", &mut out);
+        black_box(out);
+    });
+
+    let seal = time(|| {
+        let mut protocol = Protocol::new("seal");
+        protocol.mix("key", &[0u8; 32]);
+        protocol.seal("message", black_box(&mut block));
+    });
+
+    println!("mix {mix:.1}");
+    println!("derive {derive:.1}");
+    println!("seal {seal:.1}");
+}
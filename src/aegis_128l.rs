@@ -1,5 +1,16 @@
+//! An implementation of the [AEGIS-128L][] authenticated cipher.
+//!
+//! [AEGIS-128L]: https://www.ietf.org/archive/id/draft-irtf-cfrg-aegis-aead-09.html
+
+use crate::ct_eq;
 use crate::intrinsics::*;
 
+/// The length of an AEGIS-128L key in bytes.
+pub const KEY_LEN: usize = 16;
+
+/// The length of an AEGIS-128L nonce in bytes.
+pub const NONCE_LEN: usize = 16;
+
 /// The length of an AEGIS-128L block.
 const BLOCK_LEN: usize = 32;
 
@@ -13,7 +24,7 @@ pub struct Aegis128L {
 
 impl Aegis128L {
     /// Creates a new AEGIS-128L instance with the given key and nonce.
-    pub fn new(key: &[u8; AES_BLOCK_LEN], nonce: &[u8; AES_BLOCK_LEN]) -> Self {
+    pub fn new(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN]) -> Self {
         // Initialize constants.
         let c0 = load(&[
             0x00, 0x01, 0x01, 0x02, 0x03, 0x05, 0x08, 0x0d, 0x15, 0x22, 0x37, 0x59, 0x90, 0xe9,
@@ -53,7 +64,8 @@ impl Aegis128L {
     }
 
     /// Processes the given authenticated data.
-    #[cfg(all(test, feature = "std"))]
+    ///
+    /// All authenticated data must be processed before any plaintext or ciphertext.
     pub fn ad(&mut self, ad: &[u8]) {
         // Process whole blocks of associated data.
         let mut chunks = ad.chunks_exact(BLOCK_LEN);
@@ -93,6 +105,34 @@ impl Aegis128L {
         self.mc_len += in_out.len() as u64;
     }
 
+    /// Fills the given slice with keystream, overwriting its contents.
+    ///
+    /// This is exactly equivalent to encrypting a slice of zeros with [`Aegis128L::encrypt`],
+    /// including its effect on the cipher state, but writes the keystream directly to `out`.
+    pub fn prf(&mut self, out: &mut [u8]) {
+        let zero = load_64x2(0, 0);
+
+        // Write whole blocks of keystream directly to the output.
+        let mut chunks = out.chunks_exact_mut(BLOCK_LEN);
+        for chunk in chunks.by_ref() {
+            let (z0, z1) = self.keystream();
+            store_2x(chunk, z0, z1);
+            self.update(zero, zero);
+        }
+
+        // Write a partial block of keystream, if any, via a temporary buffer.
+        let rem = chunks.into_remainder();
+        if !rem.is_empty() {
+            let mut tmp = [0u8; BLOCK_LEN];
+            let (z0, z1) = self.keystream();
+            store_2x(&mut tmp, z0, z1);
+            rem.copy_from_slice(&tmp[..rem.len()]);
+            self.update(zero, zero);
+        }
+
+        self.mc_len += out.len() as u64;
+    }
+
     /// Decrypts the given slice in place.
     pub fn decrypt(&mut self, in_out: &mut [u8]) {
         // Process whole blocks of ciphertext.
@@ -110,6 +150,85 @@ impl Aegis128L {
         self.mc_len += in_out.len() as u64;
     }
 
+    /// Encrypts the given slice in place and splits it into an encrypted header of `header_len`
+    /// bytes and an encrypted body.
+    ///
+    /// This is exactly equivalent to [`Aegis128L::encrypt`] on the entire slice: the header is
+    /// encrypted and authenticated just like the body, and the split is only a convenience for
+    /// framing. Because `header_len` is not authenticated, protocols with variable-length headers
+    /// must encode the header length in the header or the associated data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `header_len` is greater than the length of `in_out`.
+    pub fn encrypt_framed<'a>(
+        &mut self,
+        header_len: usize,
+        in_out: &'a mut [u8],
+    ) -> (&'a mut [u8], &'a mut [u8]) {
+        self.encrypt(in_out);
+        in_out.split_at_mut(header_len)
+    }
+
+    /// Decrypts the given slice in place and splits it into a plaintext header of `header_len`
+    /// bytes and a plaintext body.
+    ///
+    /// This is exactly equivalent to [`Aegis128L::decrypt`] on the entire slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `header_len` is greater than the length of `in_out`.
+    pub fn decrypt_framed<'a>(
+        &mut self,
+        header_len: usize,
+        in_out: &'a mut [u8],
+    ) -> (&'a mut [u8], &'a mut [u8]) {
+        self.decrypt(in_out);
+        in_out.split_at_mut(header_len)
+    }
+
+    /// Decrypts the given slice in place, finalizes the cipher state, and returns `true` if the
+    /// 128-bit authentication tag matches `tag`.
+    ///
+    /// Associated data must be processed with [`Aegis128L::ad`] beforehand. If the tag does not
+    /// match, `in_out` is filled with zeros to avoid disclosing inauthentic plaintext.
+    #[must_use]
+    pub fn decrypt_verify(mut self, in_out: &mut [u8], tag: &[u8; 16]) -> bool {
+        // Decrypt the ciphertext.
+        self.decrypt(in_out);
+
+        // Check the tag against the counterfactual tag in constant time.
+        let (tag128, _) = self.finalize();
+        if ct_eq(tag, &tag128) {
+            true
+        } else {
+            // Zero out the inauthentic plaintext.
+            in_out.fill(0);
+            false
+        }
+    }
+
+    /// Decrypts the given slice in place, finalizes the cipher state, and returns `true` if the
+    /// 256-bit authentication tag matches `tag`.
+    ///
+    /// Associated data must be processed with [`Aegis128L::ad`] beforehand. If the tag does not
+    /// match, `in_out` is filled with zeros to avoid disclosing inauthentic plaintext.
+    #[must_use]
+    pub fn decrypt_verify_256(mut self, in_out: &mut [u8], tag: &[u8; 32]) -> bool {
+        // Decrypt the ciphertext.
+        self.decrypt(in_out);
+
+        // Check the tag against the counterfactual tag in constant time.
+        let (_, tag256) = self.finalize();
+        if ct_eq(tag, &tag256) {
+            true
+        } else {
+            // Zero out the inauthentic plaintext.
+            in_out.fill(0);
+            false
+        }
+    }
+
     /// Finalizes the cipher state into a pair of 128-bit and 256-bit authentication tags.
     pub fn finalize(mut self) -> ([u8; 16], [u8; 32]) {
         // Create a block from the associated data and message lengths, in bits, XOR it with the 3rd
@@ -130,7 +249,7 @@ impl Aegis128L {
         (tag128, tag256)
     }
 
-    #[cfg(all(test, feature = "std"))]
+    #[cfg_attr(feature = "small", inline(never))]
     fn absorb(&mut self, ai: &[u8]) {
         // Load the input blocks.
         let (ai0, ai1) = load_2x(ai);
@@ -139,10 +258,17 @@ impl Aegis128L {
         self.update(ai0, ai1);
     }
 
-    fn enc(&mut self, in_out: &mut [u8]) {
-        // Generate two blocks of keystream.
+    #[inline]
+    fn keystream(&self) -> (AesBlock, AesBlock) {
         let z0 = xor3(self.blocks[6], self.blocks[1], and(self.blocks[2], self.blocks[3]));
         let z1 = xor3(self.blocks[2], self.blocks[5], and(self.blocks[6], self.blocks[7]));
+        (z0, z1)
+    }
+
+    #[cfg_attr(feature = "small", inline(never))]
+    fn enc(&mut self, in_out: &mut [u8]) {
+        // Generate two blocks of keystream.
+        let (z0, z1) = self.keystream();
 
         // Load the plaintext blocks.
         let (xi0, xi1) = load_2x(in_out);
@@ -158,10 +284,10 @@ impl Aegis128L {
         self.update(xi0, xi1);
     }
 
+    #[cfg_attr(feature = "small", inline(never))]
     fn dec(&mut self, in_out: &mut [u8]) {
         // Generate two blocks of keystream.
-        let z0 = xor3(self.blocks[6], self.blocks[1], and(self.blocks[2], self.blocks[3]));
-        let z1 = xor3(self.blocks[2], self.blocks[5], and(self.blocks[6], self.blocks[7]));
+        let (z0, z1) = self.keystream();
 
         // Load the ciphertext blocks.
         let (ci0, ci1) = load_2x(in_out);
@@ -177,6 +303,7 @@ impl Aegis128L {
         self.update(xi0, xi1);
     }
 
+    #[cfg_attr(feature = "small", inline(never))]
     fn dec_partial(&mut self, in_out: &mut [u8]) {
         let mut tmp = [0u8; BLOCK_LEN];
 
@@ -185,8 +312,7 @@ impl Aegis128L {
         let (cn0, cn1) = load_2x(&tmp);
 
         // Generate two blocks of keystream.
-        let z0 = xor3(self.blocks[6], self.blocks[1], and(self.blocks[2], self.blocks[3]));
-        let z1 = xor3(self.blocks[2], self.blocks[5], and(self.blocks[6], self.blocks[7]));
+        let (z0, z1) = self.keystream();
 
         // XOR the ciphertext blocks with the keystream to produce padded plaintext blocks.
         let xn0 = xor(cn0, z0);
@@ -202,6 +328,7 @@ impl Aegis128L {
         self.update(xn0, xn1);
     }
 
+    #[cfg_attr(feature = "small", inline(never))]
     fn update(&mut self, m0: AesBlock, m1: AesBlock) {
         // Make a temporary copy of the last state block.
         let block7 = self.blocks[7];
@@ -222,6 +349,100 @@ impl Aegis128L {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for Aegis128L {
+    fn drop(&mut self) {
+        // Overwrite the key- and nonce-derived state with zeros. Volatile writes and a compiler
+        // fence keep the compiler from eliding the writes to memory which is about to be freed.
+        let zero = load_64x2(0, 0);
+        for block in &mut self.blocks {
+            unsafe { core::ptr::write_volatile(block, zero) };
+        }
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Encrypts the given plaintext with the given key, nonce, and associated data into a new buffer,
+/// returning the ciphertext and the 128-bit authentication tag.
+#[cfg(feature = "std")]
+pub fn encrypt_detached(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    ad: &[u8],
+    plaintext: &[u8],
+) -> (Vec<u8>, [u8; 16]) {
+    let mut aegis = Aegis128L::new(key, nonce);
+    aegis.ad(ad);
+    let mut ciphertext = plaintext.to_vec();
+    aegis.encrypt(&mut ciphertext);
+    let (tag128, _) = aegis.finalize();
+    (ciphertext, tag128)
+}
+
+/// Decrypts the given ciphertext in place with the given key, nonce, and associated data, and
+/// returns `true` if it is authenticated by the given 128-bit tag.
+///
+/// If the tag does not match, `in_out` is filled with zeros to avoid disclosing inauthentic
+/// plaintext.
+#[must_use]
+pub fn decrypt_detached(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    ad: &[u8],
+    in_out: &mut [u8],
+    tag: &[u8; 16],
+) -> bool {
+    let mut aegis = Aegis128L::new(key, nonce);
+    aegis.ad(ad);
+    aegis.decrypt_verify(in_out, tag)
+}
+
+/// A sequence of AEGIS-128L instances under a single key, each with a unique nonce.
+///
+/// Nonces are a 128-bit big-endian counter starting at zero, which guarantees no nonce is ever
+/// reused under the session's key. A session can produce at most 2^64 nonces; requesting another
+/// after that panics rather than wrapping around. A key must not be used with more than one
+/// session. Sessions deliberately don't implement `Clone`, since a clone would restart from the
+/// same counter and reuse nonces.
+pub struct Aegis128LSession {
+    key: [u8; KEY_LEN],
+    next: Option<u64>,
+}
+
+impl Aegis128LSession {
+    /// Creates a new session with the given key.
+    pub const fn new(key: [u8; KEY_LEN]) -> Self {
+        Aegis128LSession { key, next: Some(0) }
+    }
+
+    /// Returns the next unused nonce.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session has already produced 2^64 nonces.
+    pub const fn next_nonce(&mut self) -> [u8; NONCE_LEN] {
+        let counter = self.next.expect("AEGIS-128L session nonces should not be exhausted");
+        self.next = counter.checked_add(1);
+        (counter as u128).to_be_bytes()
+    }
+
+    /// Returns the next nonce and an AEGIS-128L instance initialized with it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session has already produced 2^64 nonces.
+    pub fn next_cipher(&mut self) -> ([u8; NONCE_LEN], Aegis128L) {
+        let nonce = self.next_nonce();
+        (nonce, Aegis128L::new(&self.key, &nonce))
+    }
+}
+
+impl core::fmt::Debug for Aegis128LSession {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Aegis128LSession").field("next", &self.next).finish_non_exhaustive()
+    }
+}
+
 #[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
@@ -458,6 +679,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn backend_known_answers() {
+        // Each backend (x86/x86_64, aarch64, and portable) must produce this same digest, which
+        // covers every combination of partial and whole blocks of associated data and message.
+        let mut inputs = crate::Protocol::new("com.example.aegis-kat");
+        let mut outputs = inputs.clone();
+        for ad_len in 0..=67 {
+            for msg_len in 0..=67 {
+                let key = inputs.derive_array::<KEY_LEN>("key");
+                let nonce = inputs.derive_array::<NONCE_LEN>("nonce");
+                let mut ad = vec![0u8; ad_len];
+                inputs.derive("ad", &mut ad);
+                let mut msg = vec![0u8; msg_len];
+                inputs.derive("msg", &mut msg);
+
+                let (tag128, tag256) = encrypt(&key, &nonce, &mut msg, &ad);
+                outputs.mix("ct", &msg);
+                outputs.mix("tag128", &tag128);
+                outputs.mix("tag256", &tag256);
+            }
+        }
+
+        expect!["64b2ae010d003d2c327e694c034332ca7b83721fcefc400e4728c59713636175"]
+            .assert_eq(&hex::encode(outputs.derive_array::<32>("digest")));
+    }
+
     #[test]
     fn round_trip() {
         bolero::check!().with_type::<([u8; 16], [u8; 16], Vec<u8>, Vec<u8>)>().for_each(
@@ -472,6 +719,146 @@ mod tests {
         );
     }
 
+    #[test]
+    fn session_nonces() {
+        let mut session = Aegis128LSession::new([7u8; KEY_LEN]);
+        assert_eq!([0u8; NONCE_LEN], session.next_nonce());
+        let (nonce, mut aegis) = session.next_cipher();
+        assert_eq!(1u128.to_be_bytes(), nonce);
+
+        let mut expected = Aegis128L::new(&[7u8; KEY_LEN], &nonce);
+        let mut a = [0u8; 64];
+        let mut b = [0u8; 64];
+        aegis.encrypt(&mut a);
+        expected.encrypt(&mut b);
+        assert_eq!(a, b);
+
+        session.next = Some(u64::MAX);
+        assert_eq!((u64::MAX as u128).to_be_bytes(), session.next_nonce());
+        assert!(std::panic::catch_unwind(move || session.next_nonce()).is_err());
+    }
+
+    #[test]
+    fn decrypt_verify_256() {
+        let key = [1u8; KEY_LEN];
+        let nonce = [2u8; NONCE_LEN];
+        let mut ct = *b"a secret message";
+        let (tag128, tag256) = encrypt(&key, &nonce, &mut ct, b"header");
+
+        let verify = |ad: &[u8], ct: &[u8; 16], tag: &[u8; 32]| {
+            let mut aegis = Aegis128L::new(&key, &nonce);
+            aegis.ad(ad);
+            let mut pt = *ct;
+            (aegis.decrypt_verify_256(&mut pt, tag), pt)
+        };
+
+        assert_eq!((true, *b"a secret message"), verify(b"header", &ct, &tag256));
+
+        let mut tampered = ct;
+        tampered[0] ^= 1;
+        assert_eq!((false, [0u8; 16]), verify(b"header", &tampered, &tag256));
+        assert_eq!((false, [0u8; 16]), verify(b"another header", &ct, &tag256));
+
+        let mut forged = tag256;
+        forged[31] ^= 1;
+        assert_eq!((false, [0u8; 16]), verify(b"header", &ct, &forged));
+
+        let mut truncated = [0u8; 32];
+        truncated[..16].copy_from_slice(&tag128);
+        assert_eq!((false, [0u8; 16]), verify(b"header", &ct, &truncated));
+    }
+
+    #[test]
+    #[cfg(feature = "zeroize")]
+    fn zeroize_on_drop() {
+        use core::mem::ManuallyDrop;
+
+        // Drop the cipher in place so its memory can be inspected afterwards.
+        let mut aegis = ManuallyDrop::new(Aegis128L::new(&[1u8; KEY_LEN], &[2u8; NONCE_LEN]));
+        unsafe { ManuallyDrop::drop(&mut aegis) };
+
+        let mut block = [0xffu8; 16];
+        for b in aegis.blocks {
+            store(&mut block, b);
+            assert_eq!([0u8; 16], block);
+        }
+    }
+
+    #[test]
+    fn framed() {
+        let key = [1u8; KEY_LEN];
+        let nonce = [2u8; NONCE_LEN];
+        let mut frame = *b"headera secret message";
+        let (expected_tag, _) = encrypt(&key, &nonce, &mut frame.clone(), b"");
+
+        let mut aegis = Aegis128L::new(&key, &nonce);
+        let (header, body) = aegis.encrypt_framed(6, &mut frame);
+        assert_eq!((header.len(), body.len()), (6, 16));
+        assert_eq!(aegis.finalize().0, expected_tag);
+
+        let mut aegis = Aegis128L::new(&key, &nonce);
+        let (header, body) = aegis.decrypt_framed(6, &mut frame);
+        assert_eq!(header, b"header");
+        assert_eq!(body, b"a secret message");
+        assert_eq!(aegis.finalize().0, expected_tag);
+    }
+
+    #[test]
+    fn decrypt_verify() {
+        let key = [1u8; KEY_LEN];
+        let nonce = [2u8; NONCE_LEN];
+        let mut ct = *b"a secret message";
+        let (tag, _) = encrypt(&key, &nonce, &mut ct, b"header");
+
+        let mut aegis = Aegis128L::new(&key, &nonce);
+        aegis.ad(b"header");
+        let mut pt = ct;
+        assert!(aegis.decrypt_verify(&mut pt, &tag));
+        assert_eq!(b"a secret message", &pt);
+
+        let mut aegis = Aegis128L::new(&key, &nonce);
+        aegis.ad(b"header");
+        let mut pt = ct;
+        pt[0] ^= 1;
+        assert!(!aegis.decrypt_verify(&mut pt, &tag));
+        assert_eq!([0u8; 16], pt);
+
+        let mut aegis = Aegis128L::new(&key, &nonce);
+        aegis.ad(b"another header");
+        let mut pt = ct;
+        assert!(!aegis.decrypt_verify(&mut pt, &tag));
+        assert_eq!([0u8; 16], pt);
+    }
+
+    #[test]
+    fn detached() {
+        let key = hex!("10010000000000000000000000000000");
+        let nonce = hex!("10000200000000000000000000000000");
+        let ad = hex!("0001020304050607");
+        let msg = hex!(
+            "000102030405060708090a0b0c0d0e0f"
+            "101112131415161718191a1b1c1d1e1f"
+        );
+
+        let (ct, tag) = encrypt_detached(&key, &nonce, &ad, &msg);
+        assert_eq!(
+            hex!(
+                "79d94593d8c2119d7e8fd9b8fc77845c"
+                "5c077a05b2528b6ac54b563aed8efe84"
+            ),
+            ct.as_slice()
+        );
+        assert_eq!(hex!("cc6f3372f6aa1bb82388d695c3962d9a"), tag);
+
+        let mut pt = ct.clone();
+        assert!(decrypt_detached(&key, &nonce, &ad, &mut pt, &tag));
+        assert_eq!(msg, pt.as_slice());
+
+        let mut pt = ct;
+        assert!(!decrypt_detached(&key, &nonce, b"other", &mut pt, &tag));
+        assert_eq!([0u8; 32], pt.as_slice());
+    }
+
     #[test]
     fn interop() {
         bolero::check!().with_type::<([u8; 16], [u8; 16], Vec<u8>, Vec<u8>)>().for_each(
@@ -488,6 +875,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn prf() {
+        let key = [1u8; KEY_LEN];
+        let nonce = [2u8; NONCE_LEN];
+
+        for len in 0..=100 {
+            let mut expected = Aegis128L::new(&key, &nonce);
+            let mut zeros = vec![0u8; len];
+            expected.encrypt(&mut zeros);
+
+            let mut aegis = Aegis128L::new(&key, &nonce);
+            let mut out = vec![0xffu8; len];
+            aegis.prf(&mut out);
+
+            assert_eq!(zeros, out, "len = {len}");
+            assert_eq!(expected.finalize(), aegis.finalize(), "len = {len}");
+        }
+    }
+
+    #[test]
+    fn partial_decrypt() {
+        let key = [1u8; KEY_LEN];
+        let nonce = [2u8; NONCE_LEN];
+
+        // Check partial blocks on their own and following a whole block, including the empty
+        // remainder, against the reference implementation's ciphertext and tags.
+        for prefix in [0, BLOCK_LEN] {
+            for len in 0..=BLOCK_LEN {
+                let msg = (0..prefix + len).map(|i| i as u8).collect::<Vec<u8>>();
+                let (ct, tag128) =
+                    aegis::aegis128l::Aegis128L::<16>::new(&key, &nonce).encrypt(&msg, b"ad");
+                let (_, tag256) =
+                    aegis::aegis128l::Aegis128L::<32>::new(&key, &nonce).encrypt(&msg, b"ad");
+
+                let mut aegis = Aegis128L::new(&key, &nonce);
+                aegis.ad(b"ad");
+                let mut pt = ct.clone();
+                let (whole, partial) = pt.split_at_mut(prefix);
+                aegis.decrypt(whole);
+                aegis.decrypt(partial);
+
+                assert_eq!(msg, pt, "prefix = {prefix}, len = {len}");
+                assert_eq!((tag128, tag256), aegis.finalize(), "prefix = {prefix}, len = {len}");
+            }
+        }
+    }
+
     #[test]
     fn wycheproof() {
         let set = TestSet::load(TestName::Aegis128L).expect("should have AEGIS-128L test vectors");
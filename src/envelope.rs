@@ -0,0 +1,117 @@
+//! Envelope encryption of a single payload for multiple recipients.
+//!
+//! [`multi_seal`] derives a data key from the sender's protocol, seals the payload once with a
+//! protocol keyed with the data key, and then wraps the data key for each recipient by sealing it
+//! with that recipient's protocol:
+//!
+//! ```text
+//! function multi_seal(sender, recipients, payload):
+//!   (sender, data_key) ← derive(sender, "data-key", 256)
+//!   p ← init("lockstitch.envelope")
+//!   p ← mix(p, "data-key", data_key)
+//!   (_, sealed_payload) ← seal(p, "payload", payload)
+//!   for each recipient r:
+//!     (r, wrapped_key) ← seal(r, "data-key", data_key)
+//!   (sealed_payload, wrapped_keys)
+//! ```
+//!
+//! Each recipient's protocol must already be keyed with a secret shared between the sender and that
+//! recipient (e.g. via ECDH), and the sender's protocol must contain a nonce or other unique input
+//! so that each payload is sealed with a fresh data key.
+
+use crate::{Protocol, TAG_LEN};
+
+/// The length of a data key in bytes.
+pub const DATA_KEY_LEN: usize = 32;
+
+/// The length of a wrapped data key in bytes.
+pub const WRAPPED_KEY_LEN: usize = DATA_KEY_LEN + TAG_LEN;
+
+/// Seals the given mutable slice in place with a fresh data key derived from `sender`, and wraps
+/// the data key for each protocol in `recipients` into the corresponding element of
+/// `wrapped_keys`.
+///
+/// The last [`TAG_LEN`] bytes of `payload` will be overwritten with the authentication tag.
+///
+/// # Panics
+///
+/// Panics if `recipients` and `wrapped_keys` have different lengths.
+pub fn multi_seal(
+    sender: &mut Protocol,
+    recipients: &mut [Protocol],
+    wrapped_keys: &mut [[u8; WRAPPED_KEY_LEN]],
+    payload: &mut [u8],
+) {
+    assert_eq!(recipients.len(), wrapped_keys.len(), "should have one wrapped key per recipient");
+
+    // Derive a fresh data key and seal the payload with it.
+    let data_key = sender.derive_array::<DATA_KEY_LEN>("data-key");
+    payload_protocol(&data_key).seal("payload", payload);
+
+    // Wrap the data key for each recipient.
+    for (recipient, wrapped_key) in recipients.iter_mut().zip(wrapped_keys.iter_mut()) {
+        wrapped_key[..DATA_KEY_LEN].copy_from_slice(&data_key);
+        recipient.seal("data-key", wrapped_key);
+    }
+}
+
+/// Unwraps the data key in `wrapped_key` with the recipient's protocol and uses it to open the
+/// given mutable slice in place. Returns the plaintext slice of `payload` if both the wrapped key
+/// and the payload were authenticated.
+#[must_use]
+pub fn multi_open<'ct>(
+    recipient: &mut Protocol,
+    wrapped_key: &[u8; WRAPPED_KEY_LEN],
+    payload: &'ct mut [u8],
+) -> Option<&'ct [u8]> {
+    let mut wrapped_key = *wrapped_key;
+    let data_key = recipient.open("data-key", &mut wrapped_key)?;
+    payload_protocol(data_key).open("payload", payload)
+}
+
+/// Returns a protocol keyed with the given data key for sealing or opening a payload.
+fn payload_protocol(data_key: &[u8]) -> Protocol {
+    let mut protocol = Protocol::new("lockstitch.envelope");
+    protocol.mix("data-key", data_key);
+    protocol
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn recipient(secret: &[u8]) -> Protocol {
+        let mut protocol = Protocol::new("com.example.envelope");
+        protocol.mix("shared-secret", secret);
+        protocol
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut sender = Protocol::new("com.example.envelope");
+        sender.mix("nonce", b"a unique nonce");
+
+        let mut recipients = [recipient(b"alice"), recipient(b"bob"), recipient(b"carol")];
+        let mut wrapped_keys = [[0u8; WRAPPED_KEY_LEN]; 3];
+        let mut payload = b"a large payload".to_vec();
+        payload.extend_from_slice(&[0u8; TAG_LEN]);
+        multi_seal(&mut sender, &mut recipients, &mut wrapped_keys, &mut payload);
+
+        for (secret, wrapped_key) in [&b"alice"[..], b"bob", b"carol"].iter().zip(&wrapped_keys) {
+            let mut opened = payload.clone();
+            assert_eq!(
+                Some(b"a large payload".as_slice()),
+                multi_open(&mut recipient(secret), wrapped_key, &mut opened)
+            );
+        }
+
+        // A recipient can't use another recipient's wrapped key.
+        let mut opened = payload.clone();
+        assert_eq!(None, multi_open(&mut recipient(b"alice"), &wrapped_keys[1], &mut opened));
+
+        // A tampered payload fails to open.
+        let mut opened = payload.clone();
+        opened[0] ^= 1;
+        assert_eq!(None, multi_open(&mut recipient(b"bob"), &wrapped_keys[1], &mut opened));
+    }
+}
@@ -0,0 +1,143 @@
+//! A versioned wire format for sealed messages.
+//!
+//! A framed message is laid out as:
+//!
+//! ```text
+//! version (1 byte) || ciphertext || tag (16 bytes)
+//! ```
+//!
+//! The version byte identifies the Lockstitch construction used to seal the message. It is mixed
+//! into the protocol's transcript before sealing, so it is authenticated along with the
+//! ciphertext, and [`Protocol::open_framed`] rejects unknown versions before attempting to open
+//! the message.
+
+use core::fmt;
+
+use crate::{Protocol, TAG_LEN};
+
+/// The version of the Lockstitch construction written by [`Protocol::seal_framed`].
+pub const VERSION: u8 = 1;
+
+/// The number of bytes a framed message adds to its plaintext.
+pub const OVERHEAD: usize = 1 + TAG_LEN;
+
+/// An error returned when a framed message cannot be opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The message is too short to contain a version byte and a tag.
+    TooShort,
+    /// The message was sealed with an unsupported construction version.
+    UnsupportedVersion(u8),
+    /// The message is inauthentic.
+    Unauthenticated,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::TooShort => write!(f, "framed message is too short"),
+            FrameError::UnsupportedVersion(v) => {
+                write!(f, "unsupported framed message version {v}")
+            }
+            FrameError::Unauthenticated => write!(f, "framed message is inauthentic"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FrameError {}
+
+impl Protocol {
+    /// Seals the given mutable slice in place as a framed message.
+    ///
+    /// The first byte of the slice will be overwritten with [`VERSION`] and the last [`TAG_LEN`]
+    /// bytes with the authentication tag; the plaintext goes in between.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `in_out` is shorter than [`OVERHEAD`].
+    #[inline]
+    pub fn seal_framed(&mut self, label: &str, in_out: &mut [u8]) {
+        let (version, in_out) = in_out.split_first_mut().expect("should contain a version byte");
+        *version = VERSION;
+        self.mix("version", &[VERSION]);
+        self.seal(label, in_out);
+    }
+
+    /// Opens the given framed message in place. Returns the plaintext slice of `in_out` if the
+    /// message has a supported version and was authenticated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FrameError::TooShort`] if `in_out` is shorter than [`OVERHEAD`] and
+    /// [`FrameError::UnsupportedVersion`] if its version byte is not [`VERSION`]; in both cases
+    /// the protocol state is not modified. Returns [`FrameError::Unauthenticated`] if the message
+    /// is inauthentic, in which case the plaintext is zeroed as with [`Protocol::open`].
+    #[inline]
+    pub fn open_framed<'ct>(
+        &mut self,
+        label: &str,
+        in_out: &'ct mut [u8],
+    ) -> Result<&'ct [u8], FrameError> {
+        let (&mut version, in_out) = in_out.split_first_mut().ok_or(FrameError::TooShort)?;
+        if in_out.len() < TAG_LEN {
+            return Err(FrameError::TooShort);
+        }
+
+        if version != VERSION {
+            return Err(FrameError::UnsupportedVersion(version));
+        }
+
+        self.mix("version", &[version]);
+        self.open(label, in_out).ok_or(FrameError::Unauthenticated)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn seal(message: &[u8]) -> Vec<u8> {
+        let mut protocol = Protocol::new("com.example.framed");
+        let mut framed = vec![0u8; message.len() + OVERHEAD];
+        framed[1..=message.len()].copy_from_slice(message);
+        protocol.seal_framed("message", &mut framed);
+        framed
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut framed = seal(b"a message");
+        assert_eq!(VERSION, framed[0]);
+
+        let mut protocol = Protocol::new("com.example.framed");
+        assert_eq!(Ok(b"a message".as_slice()), protocol.open_framed("message", &mut framed));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut framed = seal(b"a message");
+        framed[0] = 2;
+
+        let mut protocol = Protocol::new("com.example.framed");
+        assert_eq!(
+            Err(FrameError::UnsupportedVersion(2)),
+            protocol.open_framed("message", &mut framed)
+        );
+    }
+
+    #[test]
+    fn too_short() {
+        let mut protocol = Protocol::new("com.example.framed");
+        assert_eq!(Err(FrameError::TooShort), protocol.open_framed("message", &mut [VERSION; 16]));
+    }
+
+    #[test]
+    fn version_is_authenticated() {
+        let mut framed = seal(b"a message");
+
+        // A message framed without the version in the transcript doesn't open.
+        let mut protocol = Protocol::new("com.example.framed");
+        assert_eq!(None, protocol.open("message", &mut framed[1..]));
+    }
+}
@@ -0,0 +1,97 @@
+//! A keyed [`Hasher`] built on [`Protocol`].
+//!
+//! [`ProtocolBuildHasher`] holds a protocol keyed with a secret, and each [`ProtocolHasher`] it
+//! builds starts with a copy of that protocol. Every call to [`Hasher::write`] is a separate `Mix`
+//! operation and [`Hasher::finish`] derives a 64-bit hash from a copy of the protocol's state.
+//!
+//! Hashes are deterministic: the same key and the same sequence of written bytes always produce the
+//! same hash, on every platform. Only the hashing of a given byte sequence is platform-independent,
+//! however: the [`Hash`](core::hash::Hash) implementations of some types, such as integers and
+//! slices, write platform-dependent bytes, so hashing the same value may produce different hashes
+//! on different platforms. As the sequence of writes is framed, `write(b"ab")` followed by
+//! `write(b"c")` produces a different hash than `write(b"a")` followed by `write(b"bc")`. With a
+//! secret, uniformly random key, the hashes are unpredictable to an attacker, which makes them
+//! resistant to hash-flooding attacks. This is much slower than [`crate::short_hash`] or SipHash
+//! and is best suited to small tables where a transcript-based hash is required.
+
+use core::hash::{BuildHasher, Hasher};
+
+use crate::Protocol;
+
+/// A [`BuildHasher`] which creates [`ProtocolHasher`]s keyed with a secret.
+#[derive(Debug, Clone)]
+pub struct ProtocolBuildHasher {
+    template: Protocol,
+}
+
+impl ProtocolBuildHasher {
+    /// Creates a new builder with the given key.
+    pub fn new(key: &[u8]) -> ProtocolBuildHasher {
+        let mut template = Protocol::new("lockstitch.hasher");
+        template.mix("key", key);
+        ProtocolBuildHasher { template }
+    }
+}
+
+impl BuildHasher for ProtocolBuildHasher {
+    type Hasher = ProtocolHasher;
+
+    fn build_hasher(&self) -> ProtocolHasher {
+        ProtocolHasher { protocol: self.template.clone() }
+    }
+}
+
+/// A keyed [`Hasher`] which mixes all written data into a [`Protocol`].
+#[derive(Debug, Clone)]
+pub struct ProtocolHasher {
+    protocol: Protocol,
+}
+
+impl Hasher for ProtocolHasher {
+    fn finish(&self) -> u64 {
+        u64::from_le_bytes(self.protocol.clone().derive_array("hash"))
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.protocol.mix("data", bytes);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn known_answers() {
+        let builder = ProtocolBuildHasher::new(b"a secret key");
+        expect!["11005601286247837483"].assert_eq(&builder.hash_one("a key").to_string());
+
+        let mut hasher = builder.build_hasher();
+        hasher.write(b"one");
+        hasher.write(b"two");
+        expect!["672226446994752475"].assert_eq(&hasher.finish().to_string());
+    }
+
+    #[test]
+    fn keyed() {
+        let a = ProtocolBuildHasher::new(b"one key");
+        let b = ProtocolBuildHasher::new(b"another key");
+        assert_eq!(a.hash_one("a key"), a.hash_one("a key"));
+        assert_ne!(a.hash_one("a key"), b.hash_one("a key"));
+        assert_ne!(a.hash_one("a key"), a.hash_one("another key"));
+    }
+
+    #[test]
+    fn hash_map() {
+        let mut map = HashMap::with_hasher(ProtocolBuildHasher::new(b"a secret key"));
+        map.insert("one", 1);
+        map.insert("two", 2);
+        assert_eq!(Some(&1), map.get("one"));
+        assert_eq!(Some(&2), map.get("two"));
+        assert_eq!(None, map.get("three"));
+    }
+}
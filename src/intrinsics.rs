@@ -1,18 +1,30 @@
-#[cfg(all(target_arch = "aarch64", not(feature = "portable")))]
+#[cfg(all(
+    target_arch = "aarch64",
+    not(feature = "portable"),
+    not(feature = "portable-neon")
+))]
 pub use self::aarch64::*;
 
+#[cfg(all(target_arch = "aarch64", feature = "portable-neon", not(feature = "portable")))]
+pub use self::portable_neon::*;
+
 #[cfg(feature = "portable")]
 pub use self::portable::*;
 
 #[cfg(all(any(target_arch = "x86_64", target_arch = "x86"), not(feature = "portable")))]
 pub use self::x86_64::*;
 
-#[cfg(all(target_arch = "aarch64", not(feature = "portable")))]
+#[cfg(all(target_arch = "aarch64", not(feature = "portable"), not(feature = "portable-neon")))]
 mod aarch64;
 
 #[cfg(feature = "portable")]
 mod portable;
 
+// On aarch64, NEON is always available for the logical operations even when the AES round has to
+// be performed in software.
+#[cfg(all(target_arch = "aarch64", feature = "portable-neon", not(feature = "portable")))]
+mod portable_neon;
+
 #[cfg(all(any(target_arch = "x86_64", target_arch = "x86"), not(feature = "portable")))]
 mod x86_64;
 
@@ -0,0 +1,56 @@
+use core::arch::aarch64::{self, *};
+
+/// An AES block.
+pub use aarch64::uint8x16_t as AesBlock;
+
+/// Loads an AES block from the given slice.
+#[inline]
+pub fn load(bytes: &[u8]) -> AesBlock {
+    assert!(bytes.len() >= 16);
+    unsafe { vld1q_u8(bytes.as_ptr()) }
+}
+
+/// Loads an AES block from the two given u64 values as big-endian integers.
+#[inline]
+pub fn load_64x2(a: u64, b: u64) -> AesBlock {
+    unsafe { vreinterpretq_u8_u64(vsetq_lane_u64(b, vmovq_n_u64(a), 1)) }
+}
+
+/// Stores an AES block in the given slice.
+#[inline]
+pub fn store(bytes: &mut [u8], block: AesBlock) {
+    assert!(bytes.len() >= 16);
+    unsafe { vst1q_u8(bytes.as_mut_ptr(), block) };
+}
+
+/// Bitwise XORs the given AES blocks.
+#[inline]
+pub fn xor(a: AesBlock, b: AesBlock) -> AesBlock {
+    unsafe { veorq_u8(a, b) }
+}
+
+/// Bitwise XORs the given AES blocks.
+#[inline]
+pub fn xor3(a: AesBlock, b: AesBlock, c: AesBlock) -> AesBlock {
+    xor(a, xor(b, c))
+}
+
+/// Bitwise ANDs the given AES blocks.
+#[inline]
+pub fn and(a: AesBlock, b: AesBlock) -> AesBlock {
+    unsafe { vandq_u8(a, b) }
+}
+
+/// Performs one AES round on the given state using the given round key.
+///
+/// NEON has no AES round instruction without the cryptography extension, so the round itself is
+/// performed in software.
+#[inline]
+pub fn enc(state: AesBlock, round_key: AesBlock) -> AesBlock {
+    let mut s = aes::Block::default();
+    let mut k = aes::Block::default();
+    store(&mut s, state);
+    store(&mut k, round_key);
+    aes::hazmat::cipher_round(&mut s, &k);
+    load(&s)
+}
@@ -0,0 +1,142 @@
+//! Keys derived for specific algorithms.
+
+use core::fmt;
+
+use crate::Protocol;
+
+/// The length of the longest key any [`Algorithm`] requires, in bytes.
+const MAX_KEY_LEN: usize = 32;
+
+/// A symmetric algorithm which a [`Key`] can be derived for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// AES with a 128-bit key.
+    Aes128,
+    /// AES with a 256-bit key.
+    Aes256,
+    /// `ChaCha20` with a 256-bit key.
+    ChaCha20,
+    /// HMAC-SHA-256 with a 256-bit key.
+    HmacSha256,
+}
+
+impl Algorithm {
+    /// Returns the algorithm's name, which is mixed into the protocol before deriving its key.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Algorithm::Aes128 => "AES-128",
+            Algorithm::Aes256 => "AES-256",
+            Algorithm::ChaCha20 => "ChaCha20",
+            Algorithm::HmacSha256 => "HMAC-SHA-256",
+        }
+    }
+
+    /// Returns the length of the algorithm's key in bytes.
+    pub const fn key_len(self) -> usize {
+        match self {
+            Algorithm::Aes128 => 16,
+            Algorithm::Aes256 | Algorithm::ChaCha20 | Algorithm::HmacSha256 => 32,
+        }
+    }
+}
+
+/// A secret key derived for a specific [`Algorithm`].
+///
+/// A key's length is always the length its algorithm requires.
+#[derive(Clone)]
+pub struct Key {
+    algorithm: Algorithm,
+    bytes: [u8; MAX_KEY_LEN],
+}
+
+impl Key {
+    /// Returns the algorithm the key was derived for.
+    pub const fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Returns the key's bytes, which are exactly [`Algorithm::key_len`] bytes long.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.algorithm.key_len()]
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key").field("algorithm", &self.algorithm).finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Key {
+    fn drop(&mut self) {
+        // Overwrite the key with zeros. Volatile writes and a compiler fence keep the compiler from
+        // eliding the writes to memory which is about to be freed.
+        for b in &mut self.bytes {
+            unsafe { core::ptr::write_volatile(b, 0) };
+        }
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl Protocol {
+    /// Derives a key of the correct length for the given algorithm.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix_str`] with the label `algorithm` and
+    /// the algorithm's [name](Algorithm::name), followed by [`Protocol::derive`] with the given
+    /// label and an output of [`Algorithm::key_len`] bytes. Keys derived for different algorithms
+    /// from the same state are independent.
+    pub fn derive_for_algorithm(&mut self, label: &str, algorithm: Algorithm) -> Key {
+        self.mix_str("algorithm", algorithm.name());
+        let mut bytes = [0u8; MAX_KEY_LEN];
+        self.derive(label, &mut bytes[..algorithm.key_len()]);
+        Key { algorithm, bytes }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Algorithm; 4] =
+        [Algorithm::Aes128, Algorithm::Aes256, Algorithm::ChaCha20, Algorithm::HmacSha256];
+
+    fn protocol() -> Protocol {
+        let mut protocol = Protocol::new("com.example.key");
+        protocol.mix("secret", b"a shared secret");
+        protocol
+    }
+
+    #[test]
+    fn equivalence() {
+        for algorithm in ALGORITHMS {
+            let mut expected = protocol();
+            expected.mix_str("algorithm", algorithm.name());
+            let mut expected_key = vec![0u8; algorithm.key_len()];
+            expected.derive("key", &mut expected_key);
+
+            let key = protocol().derive_for_algorithm("key", algorithm);
+            assert_eq!(algorithm, key.algorithm());
+            assert_eq!(algorithm.key_len(), key.as_bytes().len());
+            assert_eq!(expected_key, key.as_bytes());
+        }
+    }
+
+    #[test]
+    fn distinct_algorithms() {
+        let keys = ALGORITHMS.map(|algorithm| protocol().derive_for_algorithm("key", algorithm));
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                let len = a.as_bytes().len().min(b.as_bytes().len());
+                assert_ne!(a.as_bytes()[..len], b.as_bytes()[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn debug_redacts_key() {
+        let key = protocol().derive_for_algorithm("key", Algorithm::ChaCha20);
+        assert_eq!("Key { algorithm: ChaCha20, .. }", format!("{key:?}"));
+    }
+}
@@ -4,14 +4,44 @@
 
 use crate::aegis_128l::Aegis128L;
 
-use cmov::CmovEq;
+pub use crate::key::{Algorithm, Key};
+pub use crate::state::{ProtocolState, StateError, STATE_LEN};
+pub use crate::transcribe::Transcribe;
+#[cfg(feature = "generic-array")]
+pub use sha3::digest::generic_array;
+
+use cmov::{Cmov, CmovEq};
+#[cfg(feature = "generic-array")]
+use sha3::digest::generic_array::{ArrayLength, GenericArray};
 use sha3::{
-    digest::{ExtendableOutputReset, Update, XofReader},
-    TurboShake128, TurboShake128Core,
+    digest::{Digest, ExtendableOutputReset, Update, XofReader},
+    TurboShake128, TurboShake128Core, TurboShake128Reader,
 };
 
-mod aegis_128l;
+pub mod aegis_128l;
+pub mod envelope;
+pub mod hasher;
 mod intrinsics;
+mod key;
+pub mod ratchet;
+pub mod sealer;
+mod state;
+mod transcribe;
+
+#[cfg(feature = "framed")]
+pub mod framed;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "noise")]
+pub mod noise;
+
+#[cfg(feature = "password")]
+pub mod password;
+
+#[cfg(feature = "rand_core")]
+pub mod rng;
 
 #[cfg(feature = "docs")]
 #[doc = include_str!("../design.md")]
@@ -24,20 +54,35 @@ pub mod perf {}
 /// The length of an authentication tag in bytes.
 pub const TAG_LEN: usize = 16;
 
+/// The length of the key derivation key (KDK) which replaces the transcript after each `Derive`
+/// operation, in bytes.
+pub const KDK_LEN: usize = 32;
+
 /// A stateful object providing fine-grained symmetric-key cryptographic services like hashing,
 /// message authentication codes, pseudo-random functions, authenticated encryption, and more.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Protocol {
     transcript: TurboShake128,
+    bytes_crypted: u64,
+}
+
+impl core::fmt::Debug for Protocol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Never print the transcript state, which may be derived from secrets.
+        f.debug_struct("Protocol").finish_non_exhaustive()
+    }
 }
 
 impl Protocol {
     /// Creates a new protocol with the given domain.
+    ///
+    /// The domain should be a unique, non-empty string describing the protocol (e.g.
+    /// `com.example.my-protocol`). To deliberately create a protocol without domain separation, use
+    /// [`Protocol::unkeyed`].
     #[inline]
     pub fn new(domain: &str) -> Protocol {
         // Initialize a protocol with an empty transcript.
-        let mut protocol =
-            Protocol { transcript: TurboShake128::from_core(TurboShake128Core::new(0x22)) };
+        let mut protocol = Protocol::empty();
 
         // Append the Init op header to the transcript with the domain as the label.
         //
@@ -47,6 +92,35 @@ impl Protocol {
         protocol
     }
 
+    /// Creates a new protocol with an empty domain, which provides no domain separation.
+    ///
+    /// This is exactly equivalent to `Protocol::new("")`, and exists so that protocols which
+    /// deliberately forgo domain separation say so explicitly. Protocols created this way are
+    /// distinguished from one another only by their subsequent operations.
+    #[inline]
+    pub fn unkeyed() -> Protocol {
+        Protocol::new("")
+    }
+
+    /// Returns a checkpoint of the protocol's current state, which can later be restored with
+    /// [`Protocol::restore`], e.g. before trying one branch of an interactive protocol.
+    ///
+    /// This is equivalent to cloning the protocol, and does not modify it. A checkpoint contains
+    /// the protocol's live transcript, which cannot be serialized. To persist a protocol's state
+    /// across restarts, use [`Protocol::export_state`] at the decision point instead.
+    #[inline]
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.clone())
+    }
+
+    /// Restores the protocol to the state it was in when the given checkpoint was taken.
+    ///
+    /// All operations performed since [`Protocol::checkpoint`] was called are discarded.
+    #[inline]
+    pub const fn restore(&mut self, checkpoint: Checkpoint) {
+        *self = checkpoint.0;
+    }
+
     /// Mixes the given label and slice into the protocol state.
     #[inline]
     pub fn mix(&mut self, label: &str, input: &[u8]) {
@@ -70,10 +144,340 @@ impl Protocol {
         self.mix(label, right_encode(&mut [0u8; 9], input));
     }
 
+    /// Mixes the given label and slice into the protocol state, padded to `padded_len` bytes so
+    /// that the transcript's length and structure do not depend on the length of `input`.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with
+    /// `input || zeros(padded_len - |input|) || u64_be(|input|)`. The true length is included in
+    /// fixed-width form so that inputs which differ only in trailing zeros remain distinct. Only
+    /// `padded_len` is revealed through the amount of data hashed, at the cost of always hashing
+    /// `padded_len` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` is longer than `padded_len`.
+    #[inline]
+    pub fn mix_padded(&mut self, label: &str, input: &[u8], padded_len: usize) {
+        let padding = padded_len.checked_sub(input.len()).expect("input should fit in padded_len");
+
+        // Append a Mix op header with the label to the transcript.
+        self.op_header(OpCode::Mix, label);
+
+        // Append the input and its zero padding to the transcript.
+        self.transcript.update(input);
+        for chunk in (0..padding).step_by(64) {
+            self.transcript.update(&[0u8; 64][..(padding - chunk).min(64)]);
+        }
+
+        // Append the fixed-width input length and the right-encoded padded length.
+        self.transcript.update(&(input.len() as u64).to_be_bytes());
+        self.transcript.update(right_encode(&mut [0u8; 9], (padded_len as u64 + 8) * 8));
+    }
+
+    /// Mixes the given label and a map of key/value pairs into the protocol state, independent of
+    /// the order of the pairs, e.g. to authenticate a set of HTTP headers.
+    ///
+    /// The pairs are sorted in place by key. This is then exactly equivalent to calling
+    /// [`Protocol::mix_int`] with the given label and the number of pairs, followed by
+    /// [`Protocol::mix`] with the label `key` and each key and [`Protocol::mix`] with the label
+    /// `value` and its value, in sorted order. Each key and value is framed by its length, so no
+    /// two distinct maps have the same encoding.
+    ///
+    /// Keys must be unique. A map with duplicate keys has no canonical order for its values, so two
+    /// peers with the same pairs in different orders could produce different transcripts.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if two pairs have the same key.
+    #[inline]
+    pub fn mix_sorted_pairs(&mut self, label: &str, pairs: &mut [(&[u8], &[u8])]) {
+        pairs.sort_unstable_by_key(|&(key, _)| key);
+        debug_assert!(pairs.windows(2).all(|w| w[0].0 != w[1].0), "duplicate key");
+
+        self.mix_int(label, pairs.len() as u64);
+        for (key, value) in pairs.iter() {
+            self.mix("key", key);
+            self.mix("value", value);
+        }
+    }
+
+    /// Mixes the given label and the bytes of the given iterator into the protocol state.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the collected bytes, but does
+    /// not require them to be collected into a slice first.
+    #[inline]
+    pub fn mix_iter<I: IntoIterator<Item = u8>>(&mut self, label: &str, input: I) {
+        self.mixer(label).extend(input);
+    }
+
+    /// Begins a `Mix` operation with the given label, returning a [`Mixer`] which appends all data
+    /// passed to it to the operation's input.
+    ///
+    /// The operation ends when the [`Mixer`] is dropped or [`Mixer::finish`] is called. Data may be
+    /// passed to it via [`Extend`] (e.g. from an iterator of bytes) or [`std::io::Write`], in any
+    /// combination, and the result is exactly equivalent to calling [`Protocol::mix`] with all of
+    /// the data concatenated.
+    #[inline]
+    pub fn mixer(&mut self, label: &str) -> Mixer<'_> {
+        // Append a Mix op header with the label to the transcript.
+        self.op_header(OpCode::Mix, label);
+
+        Mixer { protocol: self, len: 0 }
+    }
+
+    /// Finalizes the given digest and mixes its output into the protocol state, tagged with the
+    /// name of the digest algorithm.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix_str`] with the label
+    /// `digest-algorithm` and `algorithm`, followed by [`Protocol::mix`] with the given label and
+    /// the digest's output. Tagging the output with the algorithm ensures that commitments made
+    /// with different hash functions are never confused, even if their outputs happen to be equal.
+    /// `algorithm` should be the algorithm's standard name (e.g. `SHA-256`, `SHA3-256`, or
+    /// `BLAKE3`), and both parties must use the same name.
+    #[inline]
+    pub fn mix_digest<D: Digest>(&mut self, label: &str, algorithm: &str, digest: D) {
+        self.mix_str("digest-algorithm", algorithm);
+        self.mix(label, &digest.finalize());
+    }
+
+    /// Mixes the public keys of a Diffie-Hellman exchange and the resulting shared secret into the
+    /// protocol state.
+    ///
+    /// The public keys are given by role, not by which party is calling: `initiator_public_key` is
+    /// always the initiator's key for this exchange and `responder_public_key` is always the
+    /// responder's, so both parties mix the same inputs in the same order. For static-ephemeral
+    /// exchanges, pass each party's key for its role in the handshake (e.g. the initiator's
+    /// ephemeral key and the responder's static key).
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `dh-initiator-key`
+    /// and the initiator's public key, then with the label `dh-responder-key` and the responder's
+    /// public key, followed by [`Protocol::mix`] with the given label and the shared secret.
+    /// Binding the public keys before the shared secret ensures that the protocol's outputs depend
+    /// on which keys the shared secret was computed with, and not just on the secret itself (e.g.
+    /// a low-order point produces the same shared secret for every private key).
+    ///
+    /// This does not validate the public keys. Callers must validate them and reject
+    /// non-contributory shared secrets (e.g. an all-zero X25519 output) before calling this.
+    #[inline]
+    pub fn mix_dh(
+        &mut self,
+        label: &str,
+        initiator_public_key: &[u8],
+        responder_public_key: &[u8],
+        shared_secret: &[u8],
+    ) {
+        self.mix("dh-initiator-key", initiator_public_key);
+        self.mix("dh-responder-key", responder_public_key);
+        self.mix(label, shared_secret);
+    }
+
+    /// Mixes the given label and string into the protocol state.
+    ///
+    /// Like all inputs to [`Protocol::mix`], the UTF-8 bytes of `input` are framed with their
+    /// length, so mixing `"ab"` and then `"c"` is distinct from mixing `"a"` and then `"bc"`. This
+    /// is exactly equivalent to calling [`Protocol::mix`] with `input.as_bytes()`; it exists so
+    /// string fields can be mixed without converting them by hand. Since `Mix` already frames its
+    /// input, an additional length prefix would not make the encoding any less ambiguous.
+    #[inline]
+    pub fn mix_str(&mut self, label: &str, input: &str) {
+        self.mix(label, input.as_bytes());
+    }
+
+    /// Mixes the given label and slice into the protocol state by splitting it into at most
+    /// `segments` segments and mixing each segment in a separate fork of the protocol, in parallel.
+    ///
+    /// The data is split into consecutive segments of `⌈|data|/segments⌉` bytes, the last of which
+    /// may be shorter. This can produce fewer than `segments` segments (e.g. 9 bytes with 4
+    /// segments produces three 3-byte segments), and produces none for empty data. This is exactly
+    /// equivalent to the following:
+    ///
+    /// ```text
+    /// mix_int("segments", segments)
+    /// for each segment i of data, each ⌈|data|/segments⌉ bytes long except the last:
+    ///   fork ← clone()
+    ///   fork.mix_int("segment", i)
+    ///   fork.mix(label, segment)
+    ///   fingerprint[i] ← fork.derive(label, 32)
+    /// mix(label, fingerprint[0] || fingerprint[1] || …)
+    /// ```
+    ///
+    /// The result depends on `segments` but not on the number of threads used, so both parties
+    /// must use the same number of segments. It differs from the result of [`Protocol::mix`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `segments` is zero.
+    #[cfg(feature = "parallel")]
+    pub fn mix_parallel(&mut self, label: &str, data: &[u8], segments: usize) {
+        use rayon::prelude::*;
+
+        assert!(segments > 0, "must have at least one segment");
+
+        // Bind the segment count to the protocol state before forking.
+        self.mix_int("segments", segments as u64);
+
+        // Mix each segment in a fork bound to the segment's index and collect their fingerprints in
+        // segment order.
+        let fingerprints = data
+            .par_chunks(data.len().div_ceil(segments).max(1))
+            .enumerate()
+            .map(|(i, segment)| {
+                let mut fork = self.clone();
+                fork.mix_int("segment", i as u64);
+                fork.mix(label, segment);
+                fork.derive_array::<32>(label)
+            })
+            .collect::<Vec<[u8; 32]>>();
+
+        // Mix the fingerprints into the protocol state.
+        self.mix(label, fingerprints.as_flattened());
+    }
+
+    /// Mixes the entire contents of the given reader into the protocol state, returning the number
+    /// of bytes read.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the contents of `reader`, but
+    /// takes a trait object, for callers which only have a `dyn Read` (e.g. from a plugin).
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `reader`, in which case the protocol state is not modified.
+    #[cfg(feature = "std")]
+    pub fn mix_dyn_stream(
+        &mut self,
+        label: &str,
+        reader: &mut dyn std::io::Read,
+    ) -> std::io::Result<u64> {
+        self.copy_reader(label, reader, &mut std::io::sink())
+    }
+
+    /// Mixes the entire contents of the given reader into the protocol state while copying them to
+    /// the given writer, returning the number of bytes copied.
+    ///
+    /// This is exactly equivalent to [`Protocol::mix_dyn_stream`], except that the contents are
+    /// also written to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `reader` or `writer`, in which case the protocol state is not
+    /// modified. Some data may have been written to `writer`.
+    #[cfg(feature = "std")]
+    pub fn copy_dyn_stream(
+        &mut self,
+        label: &str,
+        reader: &mut dyn std::io::Read,
+        writer: &mut dyn std::io::Write,
+    ) -> std::io::Result<u64> {
+        self.copy_reader(label, reader, writer)
+    }
+
+    /// Mixes a canonical encoding of the given file metadata's size and modification time into the
+    /// protocol state.
+    ///
+    /// The metadata is encoded as a 20-byte value which is identical on all platforms:
+    ///
+    /// ```text
+    /// u64_be(size) || i64_be(mtime_secs) || u32_be(mtime_nanos)
+    /// ```
+    ///
+    /// `mtime_secs` is the number of whole seconds since the Unix epoch, rounded towards negative
+    /// infinity, and `mtime_nanos` is the remaining nanoseconds, in `0..1_000_000_000`. Timestamps
+    /// before the epoch therefore encode with a negative `mtime_secs`. The encoded value is mixed
+    /// with [`Protocol::mix`]. A file's path is not part of its metadata; mix it separately (e.g.
+    /// with [`Protocol::mix_str`]) if it should be authenticated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the platform does not report modification times, without modifying the
+    /// protocol state.
+    #[cfg(feature = "std")]
+    pub fn mix_metadata(&mut self, label: &str, meta: &std::fs::Metadata) -> std::io::Result<()> {
+        let encoded = encode_metadata(meta.len(), meta.modified()?);
+        self.mix(label, &encoded);
+        Ok(())
+    }
+
+    /// Mixes the given message counter into the protocol state, binding a message to its position
+    /// in a sequence.
+    ///
+    /// To reject replayed messages, the sender calls this with an incrementing counter before
+    /// sealing each message, and the receiver calls this with the counter it expects next before
+    /// opening each message. A replayed message was sealed with a stale counter and will fail to
+    /// open. This is most useful when each message is sealed with a copy of a shared protocol
+    /// state; a single protocol which seals or opens messages in order already rejects replays.
+    #[inline]
+    pub fn expect_counter(&mut self, counter: u64) {
+        self.mix_int("counter", counter);
+    }
+
+    /// Marks the beginning of a turn in an interactive protocol by mixing the given turn identifier
+    /// into the protocol state.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix_int`] with the label `turn`. Both
+    /// parties call it with the same identifier at the start of each turn (e.g. `1` before the
+    /// client's hello, `2` before the server's reply), so that a message sealed in one turn will
+    /// not open in any other, even if the turns' messages are labeled identically.
+    #[inline]
+    pub fn begin_turn(&mut self, turn_id: u32) {
+        self.mix_int("turn", turn_id.into());
+    }
+
+    /// Mixes the given Unix timestamp, rounded down to a multiple of `granularity` seconds, into
+    /// the protocol state, binding a message to a coarse time window.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix_int`] with the label `granularity`
+    /// and `granularity`, followed by [`Protocol::mix_int`] with the label `timestamp` and
+    /// `unix_secs / granularity`. The caller provides the current time, so this does not depend on
+    /// a clock (e.g. `std::time::SystemTime`).
+    ///
+    /// Two peers whose clocks fall in different windows will have different protocol states. To
+    /// tolerate clock skew and messages sent near the end of a window, the receiver tries the
+    /// adjacent windows as well, each with a copy of the protocol:
+    ///
+    /// ```
+    /// # use lockstitch::{Protocol, TAG_LEN};
+    /// # let mut sender = Protocol::new("com.example.fresh");
+    /// # let receiver = sender.clone();
+    /// # let (sent_at, now, granularity) = (1_700_000_059, 1_700_000_061, 60);
+    /// # let mut message = [0u8; 8 + TAG_LEN];
+    /// # sender.mix_timestamp(sent_at, granularity);
+    /// # sender.seal("message", &mut message);
+    /// let opened = [now - granularity, now, now + granularity].into_iter().find_map(|t| {
+    ///     let mut protocol = receiver.clone();
+    ///     protocol.mix_timestamp(t, granularity);
+    ///     protocol.open("message", &mut message.clone()).map(|pt| pt.to_vec())
+    /// });
+    /// assert!(opened.is_some());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granularity` is zero.
+    #[inline]
+    pub fn mix_timestamp(&mut self, unix_secs: u64, granularity: u64) {
+        assert!(granularity > 0, "granularity must be non-zero");
+        self.mix_int("granularity", granularity);
+        self.mix_int("timestamp", unix_secs / granularity);
+    }
+
+    /// Mixes the given label and associated data into the protocol state.
+    ///
+    /// This is an alias for [`Protocol::mix`] for authenticated-but-unencrypted data: call it
+    /// before [`Protocol::encrypt`] or [`Protocol::seal`] to authenticate cleartext (e.g. message
+    /// headers) along with the message.
+    #[inline]
+    pub fn ad(&mut self, label: &str, data: &[u8]) {
+        self.mix(label, data);
+    }
+
     /// Moves the protocol into a [`std::io::Write`] implementation, mixing all written data in a
     /// single operation and passing all writes to `inner`.
     ///
     /// Use [`MixWriter::into_inner`] to finish the operation and recover the protocol and `inner`.
+    ///
+    /// [`MixWriter`] does no buffering of its own, so the size of the buffer used to write data to
+    /// it (e.g. via [`std::io::copy`] or a [`std::io::BufWriter`]) is entirely up to the caller and
+    /// has no effect on the protocol's state.
     #[inline]
     #[cfg(feature = "std")]
     pub fn mix_writer<W: std::io::Write>(mut self, label: &str, inner: W) -> MixWriter<W> {
@@ -84,30 +488,184 @@ impl Protocol {
         MixWriter { protocol: self, inner, len: 0 }
     }
 
+    /// Mixes the contents of the given reader into the protocol state and derives a 32-byte
+    /// commitment to them, without buffering the value.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the entire contents of `reader`
+    /// and then [`Protocol::derive_array`] with the same label. The value is revealed later and
+    /// checked with [`Protocol::verify_commitment`].
+    ///
+    /// **IMPORTANT:** A commitment only hides the value if the protocol's state contains a secret,
+    /// random value (e.g. a nonce which is revealed along with the value).
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `reader`, in which case the protocol state is not modified.
+    #[cfg(feature = "std")]
+    pub fn commit_stream<R: std::io::Read>(
+        &mut self,
+        label: &str,
+        reader: R,
+    ) -> std::io::Result<[u8; 32]> {
+        self.mix_reader(label, reader)?;
+        Ok(self.derive_array(label))
+    }
+
+    /// Mixes the contents of the given reader into the protocol state and returns `true` if they
+    /// match the given commitment from [`Protocol::commit_stream`].
+    ///
+    /// The comparison is performed in constant time. The protocol's state is advanced exactly as by
+    /// [`Protocol::commit_stream`], whether or not the commitment is valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `reader`, in which case the protocol state is not modified.
+    #[cfg(feature = "std")]
+    pub fn verify_commitment<R: std::io::Read>(
+        &mut self,
+        label: &str,
+        reader: R,
+        commitment: &[u8; 32],
+    ) -> std::io::Result<bool> {
+        self.mix_reader(label, reader)?;
+        Ok(self.derive_eq(OpCode::Derive, label, commitment))
+    }
+
+    /// Ratchets the protocol and writes its state to the given writer.
+    ///
+    /// This writes the [`STATE_LEN`]-byte encoding of the state returned by
+    /// [`Protocol::export_state`]. A protocol read with [`Protocol::load`] is identical to this
+    /// protocol after it has been saved.
+    ///
+    /// **IMPORTANT:** A protocol's state is as sensitive as any secret mixed into the protocol.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `writer`.
+    #[cfg(feature = "std")]
+    pub fn save<W: std::io::Write>(&mut self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(&self.export_state().to_bytes())
+    }
+
+    /// Reads a protocol's state, as written by [`Protocol::save`], from the given reader.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`std::io::ErrorKind::InvalidData`] if the state has the wrong
+    /// magic header or an unsupported version, and any error returned by `reader` (e.g.
+    /// [`std::io::ErrorKind::UnexpectedEof`] if the state is truncated).
+    #[cfg(feature = "std")]
+    pub fn load<R: std::io::Read>(mut reader: R) -> std::io::Result<Protocol> {
+        let mut bytes = [0u8; STATE_LEN];
+        reader.read_exact(&mut bytes)?;
+        let state = ProtocolState::from_bytes(&bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Protocol::from_state(&state))
+    }
+
     /// Derives output from the protocol's current state and fills the given slice with it.
     ///
     /// The output is dependent on the protocol's prior transcript, the label, and the length of
     /// `out`.
+    ///
+    /// An empty `out` is meaningful: the protocol's state is still ratcheted, exactly as with
+    /// [`Protocol::ratchet`]. Use [`Protocol::try_derive`] to reject empty outputs instead.
     #[inline]
     pub fn derive(&mut self, label: &str, out: &mut [u8]) {
-        // Append a Derive op header with the label to the transcript.
-        //
-        //   0x03 || label || right_encode(|label|)
-        self.op_header(OpCode::Derive, label);
+        self.derive_reader(OpCode::Derive, label, out.len()).1.read(out);
+    }
 
-        // Perform a Mix operation with the output length.
-        self.mix_int("len", out.len() as u64 * 8);
+    /// Derives output from the protocol's current state and fills the given slice with it, unless
+    /// the slice is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DeriveError::EmptyOutput`] without modifying the protocol state if `out` is empty.
+    #[inline]
+    pub fn try_derive(&mut self, label: &str, out: &mut [u8]) -> Result<(), DeriveError> {
+        if out.is_empty() {
+            return Err(DeriveError::EmptyOutput);
+        }
+        self.derive(label, out);
+        Ok(())
+    }
 
-        // Hash the transcript with TurboSHAKE128 and reset it to the empty string.
-        let mut xof = self.transcript.finalize_xof_reset();
+    /// Derives `n` bytes of output from the protocol's current state and passes them to the given
+    /// digest without storing them in an intermediate buffer.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::derive`] with an `n`-byte output slice and
+    /// passing the slice to `digest`.
+    #[inline]
+    pub fn derive_to_digest<D: Update>(&mut self, label: &str, digest: &mut D, n: usize) {
+        let (_, mut xof) = self.derive_reader(OpCode::Derive, label, n);
+
+        // Pass the output to the digest in chunks of TurboSHAKE128's 168-byte rate.
+        let mut block = [0u8; 168];
+        let mut remaining = n;
+        while remaining > 0 {
+            let len = remaining.min(block.len());
+            let chunk = &mut block[..len];
+            xof.read(chunk);
+            digest.update(chunk);
+            remaining -= chunk.len();
+        }
+    }
 
-        // Generate 32+N bytes of TurboSHAKE128 output.
-        let mut kdk = [0u8; 32];
-        xof.read(&mut kdk);
-        xof.read(out);
+    /// Ratchets the protocol's state, preventing rollback.
+    ///
+    /// This is exactly equivalent to a [`Protocol::derive`] with an empty output: the transcript is
+    /// replaced with a KDK derived from it, so a compromise of the protocol's later state does not
+    /// reveal its prior state.
+    #[inline]
+    pub fn ratchet(&mut self, label: &str) {
+        self.derive(label, &mut []);
+    }
 
-        // Begin the new transcript with a Mix operation using the KDK as input.
-        self.mix("kdk", &kdk);
+    /// Irreversibly binds a new domain into the protocol, e.g. when moving from a handshake phase
+    /// to a transport phase.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix_str`] with the label `domain` and the
+    /// new domain, followed by [`Protocol::ratchet`] with the label `rotate-domain`. Unlike
+    /// starting a new protocol with [`Protocol::new`], the new phase remains bound to everything
+    /// mixed into the protocol before it, while being domain-separated from the old phase and from
+    /// other phases rotated from the same state.
+    #[inline]
+    pub fn rotate_domain(&mut self, new_domain: &str) {
+        self.mix_str("domain", new_domain);
+        self.ratchet("rotate-domain");
+    }
+
+    /// Ratchets the protocol's state `n` times, e.g. to skip ahead past missed messages.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::ratchet`] `n` times with the same label.
+    #[inline]
+    pub fn ratchet_n(&mut self, label: &str, n: u32) {
+        for _ in 0..n {
+            self.ratchet(label);
+        }
+    }
+
+    /// Derives output from the protocol's current state and fills the given slice with it, unless
+    /// the slice is longer than `max` bytes.
+    ///
+    /// Use this when the output length is computed from untrusted input.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DeriveError::TooLong`] without modifying the protocol state if `out` is longer
+    /// than `max` bytes.
+    #[inline]
+    pub fn derive_limited(
+        &mut self,
+        label: &str,
+        out: &mut [u8],
+        max: usize,
+    ) -> Result<(), DeriveError> {
+        if out.len() > max {
+            return Err(DeriveError::TooLong);
+        }
+        self.derive(label, out);
+        Ok(())
     }
 
     /// Derives output from the protocol's current state and returns it as an `N`-byte array.
@@ -118,6 +676,64 @@ impl Protocol {
         out
     }
 
+    /// Derives two `N`-byte values from the protocol's current state and returns the second if
+    /// `cond` is `true` and the first otherwise, without branching on `cond`.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::derive_array`] twice with the given label
+    /// and passing both outputs to [`ct_select`]. Both values are always derived, so the protocol's
+    /// state doesn't depend on `cond`.
+    #[inline]
+    pub fn derive_select<const N: usize>(&mut self, label: &str, cond: bool) -> [u8; N] {
+        let a = self.derive_array(label);
+        let b = self.derive_array(label);
+        ct_select(&a, &b, cond)
+    }
+
+    /// Derives output from the protocol's current state directly into a value of a plain-old-data
+    /// type, e.g. a `#[repr(C)]` struct of keys.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::derive`] with an output of
+    /// `size_of::<T>()` bytes and reinterpreting them as a `T`. The derived bytes fill the value's
+    /// raw memory, so any multi-byte integer fields take on platform-endian values, and the same
+    /// output produces different field values on big- and little-endian platforms.
+    #[cfg(feature = "bytemuck")]
+    #[inline]
+    pub fn derive_pod<T: bytemuck::Pod>(&mut self, label: &str) -> T {
+        let mut out = T::zeroed();
+        self.derive(label, bytemuck::bytes_of_mut(&mut out));
+        out
+    }
+
+    /// Derives output from the protocol's current state and returns it as a [`GenericArray`] of
+    /// `N` bytes, e.g. to key a cipher or MAC from the `RustCrypto` crates.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::derive_array`] with the same length.
+    /// [`GenericArray`] is re-exported from the version of `generic-array` used by `sha3`.
+    #[cfg(feature = "generic-array")]
+    #[inline]
+    pub fn derive_generic_array<N: ArrayLength<u8>>(&mut self, label: &str) -> GenericArray<u8, N> {
+        let mut out = GenericArray::default();
+        self.derive(label, &mut out);
+        out
+    }
+
+    /// Derives `M` keys of `N` bytes each from the protocol's current state in a single `Derive`
+    /// operation.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::derive`] with an `N*M`-byte output and
+    /// splitting it into `M` consecutive keys, and that equivalence is a stable part of the
+    /// construction. Unlike multiple calls to [`Protocol::derive_array`], the keys are derived from
+    /// the same protocol state.
+    #[inline]
+    pub fn derive_keys<const N: usize, const M: usize>(&mut self, label: &str) -> [[u8; N]; M] {
+        let mut keys = [[0u8; N]; M];
+        let (_, mut xof) = self.derive_reader(OpCode::Derive, label, N * M);
+        for key in &mut keys {
+            xof.read(key);
+        }
+        keys
+    }
+
     /// Encrypts the given slice in place.
     #[inline]
     pub fn encrypt(&mut self, label: &str, in_out: &mut [u8]) {
@@ -128,14 +744,10 @@ impl Protocol {
 
         // Perform a Mix operation with the plaintext length.
         self.mix_int("len", in_out.len() as u64 * 8);
+        self.bytes_crypted = self.bytes_crypted.saturating_add(in_out.len() as u64);
 
         // Derive an AEGIS-128L key and nonce.
-        let kn = self.derive_array::<32>("key");
-        let (k, n) = kn.split_at(16);
-        let mut aegis = Aegis128L::new(
-            k.try_into().expect("should be 16 bytes"),
-            n.try_into().expect("should be 16 bytes"),
-        );
+        let mut aegis = self.derive_aegis_128l("key");
 
         // Encrypt the plaintext.
         aegis.encrypt(in_out);
@@ -157,14 +769,10 @@ impl Protocol {
 
         // Perform a Mix operation with the plaintext length.
         self.mix_int("len", in_out.len() as u64 * 8);
+        self.bytes_crypted = self.bytes_crypted.saturating_add(in_out.len() as u64);
 
         // Derive an AEGIS-128L key and nonce.
-        let kn = self.derive_array::<32>("key");
-        let (k, n) = kn.split_at(16);
-        let mut aegis = Aegis128L::new(
-            k.try_into().expect("should be 16 bytes"),
-            n.try_into().expect("should be 16 bytes"),
-        );
+        let mut aegis = self.derive_aegis_128l("key");
 
         // Decrypt the ciphertext.
         aegis.decrypt(in_out);
@@ -184,234 +792,2910 @@ impl Protocol {
         // Split the buffer into plaintext and tag.
         let (in_out, tag128_out) = in_out.split_at_mut(in_out.len() - TAG_LEN);
 
-        // Append an AuthCrypt op header with the label to the transcript.
-        //
-        //   0x05 || label || right_encode(|label|)
-        self.op_header(OpCode::AuthCrypt, label);
-
-        // Perform a Mix operation with the plaintext length.
-        self.mix_int("len", in_out.len() as u64 * 8);
-
-        // Derive an AEGIS-128L key and nonce.
-        let kn = self.derive_array::<32>("key");
-        let (k, n) = kn.split_at(16);
-        let mut aegis = Aegis128L::new(
-            k.try_into().expect("should be 16 bytes"),
-            n.try_into().expect("should be 16 bytes"),
-        );
-
-        // Encrypt the plaintext.
-        aegis.encrypt(in_out);
-
-        // Finalize the AEGIS-128L tags.
-        let (tag128, tag256) = aegis.finalize();
-
-        // Append the 128-bit AEGIS-128L tag to the ciphertext.
-        tag128_out.copy_from_slice(&tag128);
+        // Encrypt the plaintext and append the tag to the ciphertext.
+        tag128_out.copy_from_slice(&self.seal_detached(label, in_out));
+    }
 
-        // Perform a Mix operation with the 256-bit AEGIS-128L tag.
-        self.mix("tag", &tag256);
+    /// Seals the given mutable slice in place and then derives an `N`-byte array from the
+    /// resulting protocol state.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::seal`] followed by
+    /// [`Protocol::derive_array`] with the same label, and that equivalence is a stable part of the
+    /// construction. The derived value (e.g. a message ID or the next message's nonce) is bound to
+    /// the entire transcript, including the sealed message.
+    #[inline]
+    pub fn seal_and_derive<const N: usize>(&mut self, label: &str, in_out: &mut [u8]) -> [u8; N] {
+        self.seal(label, in_out);
+        self.derive_array(label)
     }
 
     /// Opens the given mutable slice in place. Returns the plaintext slice of `in_out` if the input
     /// was authenticated. The last [`TAG_LEN`] bytes of the slice will be unmodified.
+    ///
+    /// If `in_out` is shorter than [`TAG_LEN`], returns `None` without modifying the protocol
+    /// state. The length of a ciphertext is public, so this is the only branch taken before the
+    /// tag is checked; the authenticity check itself is performed in constant time.
     #[inline]
     #[must_use]
     pub fn open<'ct>(&mut self, label: &str, in_out: &'ct mut [u8]) -> Option<&'ct [u8]> {
-        // Split the buffer into ciphertext and tag.
-        let (in_out, tag128_in) = in_out.split_at_mut(in_out.len() - TAG_LEN);
-
-        // Append an AuthCrypt op header with the label to the transcript.
-        //
-        //   0x05 || label || right_encode(|label|)
-        self.op_header(OpCode::AuthCrypt, label);
-
-        // Perform a Mix operation with the plaintext length.
-        self.mix_int("len", in_out.len() as u64 * 8);
-
-        // Derive an AEGIS-128L key and nonce.
-        let kn = self.derive_array::<32>("key");
-        let (k, n) = kn.split_at(16);
-        let mut aegis = Aegis128L::new(
-            k.try_into().expect("should be 16 bytes"),
-            n.try_into().expect("should be 16 bytes"),
-        );
+        // Split the buffer into ciphertext and tag, rejecting inputs too short to contain a tag.
+        let ct_len = in_out.len().checked_sub(TAG_LEN)?;
+        let (in_out, tag128_in) = in_out.split_at_mut(ct_len);
 
-        // Decrypt the ciphertext.
-        aegis.decrypt(in_out);
+        // Decrypt and authenticate the ciphertext.
+        self.open_detached(label, in_out, tag128_in).then_some(in_out)
+    }
 
-        // Finalize the AEGIS-128L tags.
-        let (tag128, tag256) = aegis.finalize();
+    /// Seals the given mutable slice in place, binding it to the authentication tag of the previous
+    /// message in a chain (e.g. the previous entry in an append-only log), and returns the new
+    /// message's tag.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `prev-tag` and
+    /// `prev_tag`, followed by [`Protocol::seal`]. The last [`TAG_LEN`] bytes of the slice will be
+    /// overwritten with the authentication tag, which is also returned to link the next message. A
+    /// message will only open with [`Protocol::open_linked`] given the same previous tag, so a
+    /// verifier which links each message to the tag of the last message it verified will detect
+    /// insertions, deletions, and reorderings: every message after such a change fails to open.
+    #[inline]
+    pub fn seal_linked(
+        &mut self,
+        label: &str,
+        prev_tag: &[u8; TAG_LEN],
+        in_out: &mut [u8],
+    ) -> [u8; TAG_LEN] {
+        self.mix("prev-tag", prev_tag);
 
-        // Perform a Mix operation with the 256-bit AEGIS-128L tag.
-        self.mix("tag", &tag256);
+        // Split the buffer into plaintext and tag.
+        let (in_out, tag128_out) = in_out.split_at_mut(in_out.len() - TAG_LEN);
 
-        // Check the tag against the counterfactual tag in constant time.
-        if ct_eq(tag128_in, &tag128) {
-            // If the tag is verified, then the ciphertext is authentic. Return the slice of the
-            // input which contains the plaintext.
-            Some(in_out)
-        } else {
-            // Otherwise, the ciphertext is inauthentic and we zero out the inauthentic plaintext to
-            // avoid bugs where the caller forgets to check the return value of this function and
-            // discloses inauthentic plaintext.
-            in_out.fill(0);
-            None
-        }
+        // Encrypt the plaintext and append the tag to the ciphertext.
+        let tag = self.seal_detached(label, in_out);
+        tag128_out.copy_from_slice(&tag);
+        tag
     }
 
-    /// Appends an operation header with an optional label to the protocol transcript.
+    /// Opens the given mutable slice in place, which was sealed with [`Protocol::seal_linked`] and
+    /// the same previous tag. Returns the plaintext slice of `in_out` if the input was
+    /// authenticated.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `prev-tag` and
+    /// `prev_tag`, followed by [`Protocol::open`].
     #[inline]
-    fn op_header(&mut self, op_code: OpCode, label: &str) {
-        // Append the operation code and label to the transcript:
+    #[must_use]
+    pub fn open_linked<'ct>(
+        &mut self,
+        label: &str,
+        prev_tag: &[u8; TAG_LEN],
+        in_out: &'ct mut [u8],
+    ) -> Option<&'ct [u8]> {
+        self.mix("prev-tag", prev_tag);
+        self.open(label, in_out)
+    }
+
+    /// Seals the given mutable slice in place with the authentication tag in the first
+    /// [`TAG_LEN`] bytes, followed by the ciphertext.
+    ///
+    /// This is exactly equivalent to [`Protocol::seal`] with the tag moved from the end of the
+    /// slice to its start. The first [`TAG_LEN`] bytes of the slice will be overwritten with the
+    /// authentication tag.
+    #[inline]
+    pub fn seal_tag_first(&mut self, label: &str, in_out: &mut [u8]) {
+        // Split the buffer into tag and plaintext.
+        let (tag128_out, in_out) = in_out.split_at_mut(TAG_LEN);
+
+        // Encrypt the plaintext and prepend the tag to the ciphertext.
+        tag128_out.copy_from_slice(&self.seal_detached(label, in_out));
+    }
+
+    /// Opens the given mutable slice in place, which was sealed with [`Protocol::seal_tag_first`].
+    /// Returns the plaintext slice of `in_out` if the input was authenticated. The first
+    /// [`TAG_LEN`] bytes of the slice will be unmodified.
+    ///
+    /// If `in_out` is shorter than [`TAG_LEN`], returns `None` without modifying the protocol
+    /// state.
+    #[inline]
+    #[must_use]
+    pub fn open_tag_first<'ct>(&mut self, label: &str, in_out: &'ct mut [u8]) -> Option<&'ct [u8]> {
+        // Split the buffer into tag and ciphertext, rejecting inputs too short to contain a tag.
+        if in_out.len() < TAG_LEN {
+            return None;
+        }
+        let (tag128_in, in_out) = in_out.split_at_mut(TAG_LEN);
+
+        // Decrypt and authenticate the ciphertext.
+        self.open_detached(label, in_out, tag128_in).then_some(in_out)
+    }
+
+    /// Seals the given mutable slice in place with an authentication tag truncated to `tag_len`
+    /// bytes.
+    ///
+    /// The last `tag_len` bytes of the slice will be overwritten with the authentication tag. The
+    /// tag length is mixed into the protocol before the plaintext is encrypted, so a message sealed
+    /// with one tag length will never open with another, and a truncated tag is not a prefix of the
+    /// tag [`Protocol::seal`] would produce.
+    ///
+    /// **IMPORTANT:** Truncated tags trade security for bandwidth. An attacker can forge a message
+    /// with a `b`-bit tag with probability `2^-b` per attempt, so protocols using truncated tags
+    /// must limit the number of failed attempts to open a message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag_len` is zero or greater than [`TAG_LEN`], or if `in_out` is shorter than
+    /// `tag_len`.
+    #[inline]
+    pub fn seal_with_tag_len(&mut self, label: &str, in_out: &mut [u8], tag_len: usize) {
+        assert!((1..=TAG_LEN).contains(&tag_len), "tag length must be in 1..=TAG_LEN");
+
+        // Split the buffer into plaintext and tag.
+        let (in_out, tag_out) = in_out.split_at_mut(in_out.len() - tag_len);
+
+        // Encrypt the plaintext and append the truncated tag to the ciphertext.
+        let mut aegis = self.begin_seal(label, in_out.len() as u64, Some(tag_len));
+        aegis.encrypt(in_out);
+        tag_out.copy_from_slice(&self.end_seal(aegis)[..tag_len]);
+    }
+
+    /// Opens the given mutable slice in place, which was sealed with
+    /// [`Protocol::seal_with_tag_len`] and the same `tag_len`. Returns the plaintext slice of
+    /// `in_out` if the input was authenticated. The last `tag_len` bytes of the slice will be
+    /// unmodified.
+    ///
+    /// If `in_out` is shorter than `tag_len`, returns `None` without modifying the protocol state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag_len` is zero or greater than [`TAG_LEN`].
+    #[inline]
+    #[must_use]
+    pub fn open_with_tag_len<'ct>(
+        &mut self,
+        label: &str,
+        in_out: &'ct mut [u8],
+        tag_len: usize,
+    ) -> Option<&'ct [u8]> {
+        assert!((1..=TAG_LEN).contains(&tag_len), "tag length must be in 1..=TAG_LEN");
+
+        // Split the buffer into ciphertext and tag, rejecting inputs too short to contain a tag.
+        let ct_len = in_out.len().checked_sub(tag_len)?;
+        let (in_out, tag_in) = in_out.split_at_mut(ct_len);
+
+        // Decrypt the ciphertext and calculate the counterfactual tag.
+        let mut aegis = self.begin_seal(label, in_out.len() as u64, Some(tag_len));
+        aegis.decrypt(in_out);
+        let tag128 = self.end_seal(aegis);
+
+        // Check the truncated tag against the counterfactual tag in constant time.
+        if ct_eq(tag_in, &tag128[..tag_len]) {
+            Some(in_out)
+        } else {
+            // Zero out the inauthentic plaintext.
+            in_out.fill(0);
+            None
+        }
+    }
+
+    /// Seals the given mutable slice in place, authenticating the given associated data, with an
+    /// authentication tag truncated to `tag_len` bytes.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `ad` and `ad`,
+    /// followed by [`Protocol::seal_with_tag_len`], and that equivalence is a stable part of the
+    /// construction. The associated data is not included in `in_out`; the receiver must have it to
+    /// open the message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag_len` is zero or greater than [`TAG_LEN`], or if `in_out` is shorter than
+    /// `tag_len`.
+    #[inline]
+    pub fn seal_full(&mut self, label: &str, ad: &[u8], in_out: &mut [u8], tag_len: usize) {
+        self.mix("ad", ad);
+        self.seal_with_tag_len(label, in_out, tag_len);
+    }
+
+    /// Opens the given mutable slice in place, which was sealed with [`Protocol::seal_full`] and
+    /// the same associated data and `tag_len`. Returns the plaintext slice of `in_out` if the input
+    /// was authenticated.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `ad` and `ad`,
+    /// followed by [`Protocol::open_with_tag_len`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag_len` is zero or greater than [`TAG_LEN`].
+    #[inline]
+    #[must_use]
+    pub fn open_full<'ct>(
+        &mut self,
+        label: &str,
+        ad: &[u8],
+        in_out: &'ct mut [u8],
+        tag_len: usize,
+    ) -> Option<&'ct [u8]> {
+        self.mix("ad", ad);
+        self.open_with_tag_len(label, in_out, tag_len)
+    }
+
+    /// Opens a copy of the given sealed slice. Returns the plaintext, without the tag, if the input
+    /// was authenticated.
+    ///
+    /// This is exactly equivalent to [`Protocol::open`] on a copy of `sealed`, for callers which
+    /// receive an immutable buffer and want an owned plaintext.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn open_to_vec(&mut self, label: &str, sealed: &[u8]) -> Option<Vec<u8>> {
+        let mut in_out = sealed.to_vec();
+        let pt_len = self.open(label, &mut in_out)?.len();
+        in_out.truncate(pt_len);
+        Some(in_out)
+    }
+
+    /// Seals the given plaintext padded to a multiple of `bucket` bytes, hiding its exact length,
+    /// and returns the sealed padded plaintext.
+    ///
+    /// The padded plaintext is `u64_be(|plaintext|) || plaintext || zeros`, with enough zeros to
+    /// make its length a multiple of `bucket`. It is sealed with [`Protocol::seal`], so the length
+    /// prefix and padding are authenticated along with the plaintext. The output is
+    /// [`TAG_LEN`] bytes longer than the padded plaintext. Open it with [`Protocol::open_padded`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket` is zero.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn seal_padded(&mut self, label: &str, plaintext: &[u8], bucket: usize) -> Vec<u8> {
+        assert!(bucket > 0, "bucket size must be non-zero");
+
+        let padded_len = (8 + plaintext.len()).next_multiple_of(bucket);
+        let mut out = Vec::with_capacity(padded_len + TAG_LEN);
+        out.extend_from_slice(&(plaintext.len() as u64).to_be_bytes());
+        out.extend_from_slice(plaintext);
+        out.resize(padded_len + TAG_LEN, 0);
+        self.seal(label, &mut out);
+        out
+    }
+
+    /// Opens a copy of the given slice sealed with [`Protocol::seal_padded`]. Returns the exact
+    /// plaintext, without the length prefix or padding, if the input was authenticated and well
+    /// formed.
+    ///
+    /// The protocol's state is advanced exactly as by [`Protocol::open`], whether or not the input
+    /// was authenticated. If the padded plaintext is malformed, it is zeroed before it is dropped.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn open_padded(&mut self, label: &str, sealed: &[u8]) -> Option<Vec<u8>> {
+        let mut padded = self.open_to_vec(label, sealed)?;
+        let len = padded.split_first_chunk::<8>().and_then(|(len, rest)| {
+            let len =
+                usize::try_from(u64::from_be_bytes(*len)).ok().filter(|&len| len <= rest.len())?;
+            rest[len..].iter().all(|&b| b == 0).then_some(len)
+        });
+        let Some(len) = len else {
+            padded.fill(0);
+            return None;
+        };
+
+        // Move the plaintext to the front and zero the bytes left behind it.
+        padded.copy_within(8..8 + len, 0);
+        padded[len..].fill(0);
+        padded.truncate(len);
+        Some(padded)
+    }
+
+    /// Seals a copy of the given plaintext and writes the ciphertext and authentication tag to the
+    /// given writer, without buffering the whole ciphertext.
+    ///
+    /// The plaintext is encrypted in fixed-size chunks, each written as soon as it is encrypted,
+    /// and followed by the [`TAG_LEN`]-byte tag. The output and the protocol's state are exactly
+    /// as if [`Protocol::seal`] were called on a copy of the plaintext with room for the tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by `writer`, in which case the output is incomplete. The
+    /// protocol's state is advanced exactly as by [`Protocol::seal`] whether or not writing fails.
+    #[cfg(feature = "std")]
+    pub fn seal_to_writer<W: std::io::Write>(
+        &mut self,
+        label: &str,
+        plaintext: &[u8],
+        mut writer: W,
+    ) -> std::io::Result<()> {
+        let mut aegis = self.begin_seal(label, plaintext.len() as u64, None);
+
+        // Encrypt the plaintext in chunks which are a multiple of the AEGIS-128L block length,
+        // writing each chunk of ciphertext until the writer fails. The remaining chunks are still
+        // encrypted so that the protocol's state doesn't depend on the writer.
+        let mut result = Ok(());
+        let mut buf = [0u8; 4096];
+        for chunk in plaintext.chunks(buf.len()) {
+            let ciphertext = &mut buf[..chunk.len()];
+            ciphertext.copy_from_slice(chunk);
+            aegis.encrypt(ciphertext);
+            if result.is_ok() {
+                result = writer.write_all(ciphertext);
+            }
+        }
+
+        let tag = self.end_seal(aegis);
+        result?;
+        writer.write_all(&tag)
+    }
+
+    /// Seals exactly `len` bytes read from the given reader, writing the ciphertext and
+    /// authentication tag to the given writer, e.g. for messages too large to hold in memory.
+    ///
+    /// The length is a `u64` rather than a `usize`, so messages longer than 4 GiB can be sealed on
+    /// 32-bit platforms. The output and the protocol's state are exactly as if [`Protocol::seal`]
+    /// were called on the `len` bytes of plaintext with room for the tag, on any platform.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `reader` or `writer`, or an error of kind
+    /// [`std::io::ErrorKind::UnexpectedEof`] if `reader` returns fewer than `len` bytes. The
+    /// protocol's state is left in the middle of the operation and must not be used further.
+    #[cfg(feature = "std")]
+    pub fn seal_stream<R: std::io::Read, W: std::io::Write>(
+        &mut self,
+        label: &str,
+        len: u64,
+        mut reader: R,
+        mut writer: W,
+    ) -> std::io::Result<()> {
+        let mut aegis = self.begin_seal(label, len, None);
+
+        // Encrypt the plaintext in chunks which are a multiple of the AEGIS-128L block length.
+        const BUF_LEN: usize = 4096;
+        let mut buf = [0u8; BUF_LEN];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = &mut buf[..remaining.min(BUF_LEN as u64) as usize];
+            reader.read_exact(chunk)?;
+            aegis.encrypt(chunk);
+            writer.write_all(chunk)?;
+            remaining -= chunk.len() as u64;
+        }
+
+        let tag = self.end_seal(aegis);
+        writer.write_all(&tag)
+    }
+
+    /// Seals the given mutable slice in place as a frame with an 8-byte length prefix.
+    ///
+    /// The frame is laid out as `prefix || ciphertext || tag`, where the prefix is the big-endian
+    /// 64-bit length of the plaintext. The first 8 bytes of the slice will be overwritten with the
+    /// prefix, which is either mixed into the protocol and written in the clear or encrypted,
+    /// depending on `mode`, and the last [`TAG_LEN`] bytes will be overwritten with the
+    /// authentication tag, which covers the prefix as well as the plaintext.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `in_out` is shorter than `8 + TAG_LEN` bytes.
+    #[inline]
+    pub fn seal_with_len_prefix(&mut self, label: &str, mode: LenPrefix, in_out: &mut [u8]) {
+        let (prefix, in_out) = in_out.split_at_mut(8);
+        let len = in_out.len().checked_sub(TAG_LEN).expect("should contain a tag") as u64;
+        prefix.copy_from_slice(&len.to_be_bytes());
+        match mode {
+            LenPrefix::Clear => self.mix("len-prefix", prefix),
+            LenPrefix::Encrypted => self.encrypt("len-prefix", prefix),
+        }
+        self.seal(label, in_out);
+    }
+
+    /// Opens the given frame, as sealed by [`Protocol::seal_with_len_prefix`], in place. Returns
+    /// the plaintext slice of `in_out` if the frame was authenticated and its length prefix matches
+    /// the length of the frame. The first 8 and last [`TAG_LEN`] bytes of the slice will be
+    /// unmodified. If the frame is not authenticated or its length prefix doesn't match, the
+    /// plaintext is zeroed.
+    ///
+    /// If `in_out` is shorter than `8 + TAG_LEN` bytes, returns `None` without modifying the
+    /// protocol state.
+    #[inline]
+    #[must_use]
+    pub fn open_with_len_prefix<'ct>(
+        &mut self,
+        label: &str,
+        mode: LenPrefix,
+        in_out: &'ct mut [u8],
+    ) -> Option<&'ct [u8]> {
+        if in_out.len() < 8 + TAG_LEN {
+            return None;
+        }
+
+        let (prefix, in_out) = in_out.split_at_mut(8);
+        let mut len = [0u8; 8];
+        len.copy_from_slice(prefix);
+        match mode {
+            LenPrefix::Clear => self.mix("len-prefix", &len),
+            LenPrefix::Encrypted => self.decrypt("len-prefix", &mut len),
+        }
+
+        let expected_len = (in_out.len() - TAG_LEN) as u64;
+        let pt_len = self.open(label, in_out)?.len();
+        if u64::from_be_bytes(len) == expected_len {
+            Some(&in_out[..pt_len])
+        } else {
+            // Zero the plaintext of a malformed frame, as open does for inauthentic input.
+            in_out.fill(0);
+            None
+        }
+    }
+
+    /// Seals the given mutable slice in place deterministically, without requiring a nonce.
+    ///
+    /// The last [`TAG_LEN`] bytes of the slice will be overwritten with a synthetic IV derived from
+    /// the protocol's transcript and the plaintext, which is then mixed into the protocol before
+    /// the plaintext is encrypted. The same transcript and plaintext always produce the same
+    /// ciphertext, revealing only whether two messages are exact duplicates.
+    #[inline]
+    pub fn seal_siv(&mut self, label: &str, in_out: &mut [u8]) {
+        // Split the buffer into plaintext and synthetic IV.
+        let (in_out, siv_out) = in_out.split_at_mut(in_out.len() - TAG_LEN);
+
+        // Derive a synthetic IV from a copy of the transcript and the plaintext.
+        let siv = self.synthetic_iv(label, in_out);
+        siv_out.copy_from_slice(&siv);
+
+        // Perform a Mix operation with the synthetic IV and encrypt the plaintext.
+        self.mix("siv", &siv);
+        self.encrypt(label, in_out);
+    }
+
+    /// Opens the given mutable slice in place. Returns the plaintext slice of `in_out` if the
+    /// input was authenticated. The last [`TAG_LEN`] bytes of the slice will be unmodified.
+    ///
+    /// If `in_out` is shorter than [`TAG_LEN`], returns `None` without modifying the protocol
+    /// state.
+    #[inline]
+    #[must_use]
+    pub fn open_siv<'ct>(&mut self, label: &str, in_out: &'ct mut [u8]) -> Option<&'ct [u8]> {
+        // Split the buffer into ciphertext and synthetic IV, rejecting inputs too short to
+        // contain one.
+        let ct_len = in_out.len().checked_sub(TAG_LEN)?;
+        let (in_out, siv_in) = in_out.split_at_mut(ct_len);
+
+        // Keep a copy of the transcript to recompute the synthetic IV.
+        let prior = self.clone();
+
+        // Perform a Mix operation with the received synthetic IV and decrypt the ciphertext.
+        self.mix("siv", siv_in);
+        self.decrypt(label, in_out);
+
+        // Check the received synthetic IV against the counterfactual synthetic IV in constant
+        // time.
+        if ct_eq(siv_in, &prior.synthetic_iv(label, in_out)) {
+            Some(in_out)
+        } else {
+            // Zero out the inauthentic plaintext.
+            in_out.fill(0);
+            None
+        }
+    }
+
+    /// Derives a synthetic IV for the given plaintext from a copy of the protocol.
+    #[inline]
+    fn synthetic_iv(&self, label: &str, plaintext: &[u8]) -> [u8; TAG_LEN] {
+        let mut protocol = self.clone();
+        protocol.mix(label, plaintext);
+        protocol.derive_array("siv")
+    }
+
+    /// Seals a record of cleartext and secret fields in place, returning a single authentication
+    /// tag for the whole record.
+    ///
+    /// Each [`Field::Clear`] is mixed into the protocol and each [`Field::Secret`] is encrypted, in
+    /// order, after which a [`TAG_LEN`]-byte tag is derived with the given label.
+    #[inline]
+    pub fn seal_record(&mut self, label: &str, fields: &mut [Field<'_>]) -> [u8; TAG_LEN] {
+        for field in fields.iter_mut() {
+            match field {
+                Field::Clear(label, data) => self.mix(label, data),
+                Field::Secret(label, data) => self.encrypt(label, data),
+            }
+        }
+        self.derive_array(label)
+    }
+
+    /// Opens a record of cleartext and secret fields in place, returning `true` if the record was
+    /// authenticated by `tag`.
+    ///
+    /// The fields must be given in the same order and with the same labels as they were sealed. If
+    /// the record is inauthentic, every [`Field::Secret`] is filled with zeros.
+    #[inline]
+    #[must_use]
+    pub fn open_record(&mut self, label: &str, fields: &mut [Field<'_>], tag: &[u8]) -> bool {
+        for field in fields.iter_mut() {
+            match field {
+                Field::Clear(label, data) => self.mix(label, data),
+                Field::Secret(label, data) => self.decrypt(label, data),
+            }
+        }
+
+        // Check the tag against the counterfactual tag in constant time.
+        if ct_eq(tag, &self.derive_array::<TAG_LEN>(label)) {
+            true
+        } else {
+            // Zero out the inauthentic plaintext.
+            for field in fields.iter_mut() {
+                if let Field::Secret(_, data) = field {
+                    data.fill(0);
+                }
+            }
+            false
+        }
+    }
+
+    /// Returns the total number of bytes this protocol has encrypted or decrypted with
+    /// [`Protocol::encrypt`], [`Protocol::decrypt`], [`Protocol::seal`], and [`Protocol::open`]
+    /// (and the methods built on them), not counting tags.
+    ///
+    /// This is useful for enforcing data limits, e.g. by ratcheting or rekeying after a given
+    /// volume. The count is tracked per instance and is copied when the protocol is cloned; it is
+    /// not part of the transcript. Data processed with an AEGIS-128L instance from
+    /// [`Protocol::derive_aegis`] or [`Protocol::derive_cipher`] is not counted.
+    #[inline]
+    #[must_use]
+    pub const fn bytes_crypted(&self) -> u64 {
+        self.bytes_crypted
+    }
+
+    /// Returns a copy of the protocol positioned at its current transcript, for speculatively
+    /// opening messages without disturbing the protocol itself.
+    ///
+    /// [`Protocol::open`] advances the protocol's state whether or not the message is authentic,
+    /// so a failed attempt leaves the protocol out of sync with its peer. When checking one or more
+    /// candidate messages, open each on a verification clone and, once one authenticates, replace
+    /// the authoritative protocol with that clone.
+    #[inline]
+    #[must_use]
+    pub fn verify_clone(&self) -> Protocol {
+        self.clone()
+    }
+
+    /// Derives a standalone AEGIS-128L instance from a clone of the protocol's current state and
+    /// the given context, leaving the protocol itself unmodified.
+    ///
+    /// This allows many independent records to be encrypted under the same protocol state (e.g.
+    /// by worker threads) without holding `&mut self`. Unlike [`Protocol::encrypt`] and
+    /// [`Protocol::seal`], nothing about the record is fed back into the protocol's transcript: the
+    /// protocol's future outputs do not depend on the record or the cipher's tags. This is a
+    /// deliberate trade-off for parallelism; the context must be unique for each record (e.g. a
+    /// record number) or the keystream will be reused.
+    ///
+    /// The cipher is derived by performing a `Mix` operation with the label and context on a clone
+    /// of the protocol and then deriving a key and nonce from it exactly as
+    /// [`Protocol::derive_aegis`] does with the label `key`, so the key is never equal to the
+    /// output of a [`Protocol::derive`] on the clone.
+    #[inline]
+    pub fn derive_cipher(&self, label: &str, context: &[u8]) -> Aegis128L {
+        let mut protocol = self.clone();
+        protocol.mix(label, context);
+        protocol.derive_cipher_op("key")
+    }
+
+    /// Derives a 128-bit identifier from the protocol's current state, formatted as a version 4
+    /// (random) UUID.
+    ///
+    /// This derives 16 bytes of output with the given label and sets the UUID version bits (the
+    /// high nibble of byte 6 to `0b0100`) and variant bits (the high two bits of byte 8 to `0b10`),
+    /// leaving 122 bits derived from the protocol's transcript.
+    #[inline]
+    pub fn derive_id(&mut self, label: &str) -> [u8; 16] {
+        let mut id = self.derive_array::<16>(label);
+        id[6] = (id[6] & 0x0f) | 0x40;
+        id[8] = (id[8] & 0x3f) | 0x80;
+        id
+    }
+
+    /// Derives an acknowledgement from the protocol's current state, proving that the protocol has
+    /// processed all prior inputs (e.g. a request in a challenge-response protocol).
+    ///
+    /// This is exactly equivalent to [`Protocol::derive_array`]. The peer checks the
+    /// acknowledgement with [`Protocol::verify_acknowledgement`] using the same label.
+    #[inline]
+    pub fn acknowledge<const N: usize>(&mut self, label: &str) -> [u8; N] {
+        self.derive_array(label)
+    }
+
+    /// Derives a counterfactual acknowledgement from the protocol's current state and returns
+    /// `true` if it is equal to `ack`.
+    ///
+    /// The comparison is performed in constant time. The protocol's state is advanced exactly as
+    /// by [`Protocol::acknowledge`] with an output as long as `ack`, whether or not the
+    /// acknowledgement is valid.
+    #[inline]
+    #[must_use]
+    pub fn verify_acknowledgement(&mut self, label: &str, ack: &[u8]) -> bool {
+        self.derive_eq(OpCode::Derive, label, ack)
+    }
+
+    /// Derives a MAC from the protocol's current state, proving to a peer that both transcripts
+    /// are identical. Equivalent to STROBE's `send_MAC` operation.
+    ///
+    /// Unlike [`Protocol::derive`], this uses its own operation code, so a MAC is always distinct
+    /// from derived output with the same label. The peer checks the MAC with
+    /// [`Protocol::recv_mac`] using the same label.
+    #[inline]
+    pub fn send_mac(&mut self, label: &str) -> [u8; TAG_LEN] {
+        let mut mac = [0u8; TAG_LEN];
+        self.derive_reader(OpCode::Mac, label, TAG_LEN).1.read(&mut mac);
+        mac
+    }
+
+    /// Derives a counterfactual MAC from the protocol's current state and returns `true` if it is
+    /// equal to `mac`. Equivalent to STROBE's `recv_MAC` operation.
+    ///
+    /// The comparison is performed in constant time. The protocol's state is advanced exactly as
+    /// by [`Protocol::send_mac`], whether or not the MAC is valid, so the caller must abort the
+    /// session if this returns `false`.
+    #[inline]
+    #[must_use]
+    pub fn recv_mac(&mut self, label: &str, mac: &[u8]) -> bool {
+        // Always derive TAG_LEN bytes so the transcript matches the sender's regardless of `mac`.
+        let valid_len = mac.len() == TAG_LEN;
+        let mut expected = [0u8; TAG_LEN];
+        expected[..mac.len().min(TAG_LEN)].copy_from_slice(&mac[..mac.len().min(TAG_LEN)]);
+        self.derive_eq(OpCode::Mac, label, &expected) & valid_len
+    }
+
+    /// Authenticates the given control data without encrypting it, returning a MAC to send along
+    /// with it, e.g. for pings or acknowledgements in a transport which also seals data frames.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the given label and the control
+    /// data, followed by [`Protocol::send_mac`] with the same label. Control frames and data frames
+    /// sealed with [`Protocol::seal`] share the protocol's transcript, so they are ordered and
+    /// bound together, and their distinct operations keep one from being accepted as the other. The
+    /// peer checks the frame with [`Protocol::recv_control`] using the same label.
+    #[inline]
+    pub fn send_control(&mut self, label: &str, control: &[u8]) -> [u8; TAG_LEN] {
+        self.mix(label, control);
+        self.send_mac(label)
+    }
+
+    /// Mixes the given control data into the protocol state and returns `true` if `mac` is the MAC
+    /// returned by [`Protocol::send_control`] for it.
+    ///
+    /// The comparison is performed in constant time. The protocol's state is advanced exactly as
+    /// by [`Protocol::send_control`], whether or not the MAC is valid.
+    #[inline]
+    #[must_use]
+    pub fn recv_control(&mut self, label: &str, control: &[u8], mac: &[u8]) -> bool {
+        self.mix(label, control);
+        self.recv_mac(label, mac)
+    }
+
+    /// Returns a short fingerprint of the protocol's current state which peers can compare to
+    /// detect a desynchronized session, without modifying the protocol's state.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::derive_array`] with the label
+    /// `sync-beacon` on a copy of the protocol. If the protocol's state depends on a secret key,
+    /// the beacon is a pseudorandom function of the key and the transcript, and revealing it
+    /// reveals nothing about either beyond whether two protocols are in the same state. If the
+    /// state contains no secret, anyone who can guess the transcript can compute the beacon. Two
+    /// beacons from the same state are identical, so an observer can tell when a protocol's state
+    /// has not changed between beacons.
+    #[inline]
+    #[must_use]
+    pub fn sync_beacon(&self) -> [u8; 16] {
+        self.clone().derive_array("sync-beacon")
+    }
+
+    /// Derives a 32-byte key for the object with the given ID, without modifying the protocol's
+    /// state, e.g. to encrypt each object in an object store under its own key.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `object-key` and the
+    /// object ID on a copy of the protocol, followed by [`Protocol::derive_array`] with the label
+    /// `key`. Since the protocol is never advanced, the same ID always yields the same key.
+    #[inline]
+    #[must_use]
+    pub fn object_key(&self, object_id: &[u8]) -> [u8; 32] {
+        let mut protocol = self.clone();
+        protocol.mix("object-key", object_id);
+        protocol.derive_array("key")
+    }
+
+    /// Derives output from the protocol's current state with the same length as `received` and
+    /// returns `true` if it is equal to `received`, e.g. to validate a session resumption token.
+    ///
+    /// The comparison is performed in constant time. The protocol's state is advanced exactly as by
+    /// [`Protocol::derive`] with an output as long as `received`, whether or not it is valid. An
+    /// empty `received` is never valid, since it would match any protocol state.
+    #[inline]
+    #[must_use]
+    pub fn verify_derived(&mut self, label: &str, received: &[u8]) -> bool {
+        self.derive_eq(OpCode::Derive, label, received) & !received.is_empty()
+    }
+
+    /// Derives an AES-256-GCM key and nonce from the protocol's current state, for use with
+    /// systems which require AES-256-GCM.
+    ///
+    /// This derives 44 bytes of output with the given label and returns the first 32 bytes as the
+    /// key and the last 12 bytes as the nonce. This split is a stable part of the construction.
+    #[inline]
+    pub fn derive_aes256gcm(&mut self, label: &str) -> ([u8; 32], [u8; 12]) {
+        let kn = self.derive_array::<44>(label);
+        let (k, n) = kn.split_at(32);
+        (k.try_into().expect("should be 32 bytes"), n.try_into().expect("should be 12 bytes"))
+    }
+
+    /// Derives an AEGIS-128L instance from the protocol's current state for encrypting or
+    /// decrypting a bulk payload outside of the protocol.
+    ///
+    /// The key and nonce are produced by a `DeriveCipher` operation with the given label, so the
+    /// instance is bound to the protocol's prior transcript. `DeriveCipher` has its own operation
+    /// code, so the key and nonce are never equal to the output of a [`Protocol::derive`] with the
+    /// same label. Once the payload has been processed, pass the instance to
+    /// [`Protocol::seal_aegis`] (when encrypting) or [`Protocol::open_aegis`] (when decrypting)
+    /// with the same label to fold its tag back into the transcript.
+    #[inline]
+    pub fn derive_aegis(&mut self, label: &str) -> Aegis128L {
+        self.derive_cipher_op(label)
+    }
+
+    /// Finalizes an AEGIS-128L instance returned by [`Protocol::derive_aegis`] after encryption,
+    /// mixing its 256-bit tag into the protocol state and returning its 128-bit tag.
+    ///
+    /// The returned tag should be sent along with the ciphertext and checked by the recipient with
+    /// [`Protocol::open_aegis`].
+    #[inline]
+    pub fn seal_aegis(&mut self, label: &str, aegis: Aegis128L) -> [u8; TAG_LEN] {
+        // Finalize the AEGIS-128L tags.
+        let (tag128, tag256) = aegis.finalize();
+
+        // Perform a Mix operation with the 256-bit AEGIS-128L tag.
+        self.mix(label, &tag256);
+
+        tag128
+    }
+
+    /// Finalizes an AEGIS-128L instance returned by [`Protocol::derive_aegis`] after decryption,
+    /// mixing its 256-bit tag into the protocol state and returning `true` if its 128-bit tag
+    /// matches `tag`.
+    ///
+    /// Unlike [`Protocol::open`], the plaintext has already been released by the time the tag is
+    /// checked: if this returns `false`, the caller must discard everything decrypted with `aegis`.
+    #[inline]
+    #[must_use]
+    pub fn open_aegis(&mut self, label: &str, aegis: Aegis128L, tag: &[u8; TAG_LEN]) -> bool {
+        // Finalize the AEGIS-128L tags.
+        let (tag128, tag256) = aegis.finalize();
+
+        // Perform a Mix operation with the 256-bit AEGIS-128L tag.
+        self.mix(label, &tag256);
+
+        // Check the tag against the counterfactual tag in constant time.
+        ct_eq(tag, &tag128)
+    }
+
+    /// Performs a `Derive` operation for an `n`-byte output and returns the new KDK and the reader
+    /// for the output.
+    #[inline]
+    fn derive_reader(
+        &mut self,
+        op_code: OpCode,
+        label: &str,
+        n: usize,
+    ) -> ([u8; KDK_LEN], TurboShake128Reader) {
+        // Append an op header with the label to the transcript.
         //
         //   op_code || label || right_encode(|label|)
-        self.transcript.update(&[op_code as u8]);
-        self.transcript.update(label.as_bytes());
-        self.transcript.update(right_encode(&mut [0u8; 9], label.len() as u64 * 8));
+        self.op_header(op_code, label);
+
+        // Perform a Mix operation with the output length.
+        self.mix_int("len", n as u64 * 8);
+
+        // Hash the transcript with TurboSHAKE128 and reset it to the empty string.
+        let mut xof = self.transcript.finalize_xof_reset();
+
+        // Generate 32 bytes of TurboSHAKE128 output for a KDK.
+        let mut kdk = [0u8; KDK_LEN];
+        xof.read(&mut kdk);
+
+        // Begin the new transcript with a Mix operation using the KDK as input.
+        self.mix("kdk", &kdk);
+
+        // Return the KDK and the reader for the remaining N bytes of output.
+        (kdk, xof)
+    }
+
+    /// Mixes the contents of the given reader into the protocol state in a single operation. If the
+    /// reader returns an error, the protocol state is not modified.
+    #[cfg(feature = "std")]
+    fn mix_reader<R: std::io::Read>(&mut self, label: &str, mut reader: R) -> std::io::Result<()> {
+        self.copy_reader(label, &mut reader, &mut std::io::sink()).map(|_| ())
+    }
+
+    /// Mixes the contents of the given reader into the protocol state in a single operation while
+    /// copying them to the given writer, returning the number of bytes copied. If either returns an
+    /// error, the protocol state is not modified.
+    #[cfg(feature = "std")]
+    fn copy_reader<R: std::io::Read + ?Sized, W: std::io::Write + ?Sized>(
+        &mut self,
+        label: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> std::io::Result<u64> {
+        let mut writer = self.clone().mix_writer(label, writer);
+        let n = std::io::copy(reader, &mut writer)?;
+        (*self, _) = writer.into_inner();
+        Ok(n)
+    }
+
+    /// Derives output from the protocol's current state with the same length as `expected` and
+    /// compares it to `expected` in constant time, without storing the output in a buffer as long
+    /// as `expected`.
+    #[inline]
+    fn derive_eq(&mut self, op_code: OpCode, label: &str, expected: &[u8]) -> bool {
+        let (_, mut xof) = self.derive_reader(op_code, label, expected.len());
+        let mut block = [0u8; 168];
+        let mut res = 1;
+        for chunk in expected.chunks(block.len()) {
+            let block = &mut block[..chunk.len()];
+            xof.read(block);
+            chunk.cmovne(block, 0, &mut res);
+        }
+        res != 0
+    }
+
+    /// Encrypts the given plaintext in place as part of a `Seal` operation and returns the
+    /// 128-bit authentication tag.
+    #[inline]
+    fn seal_detached(&mut self, label: &str, in_out: &mut [u8]) -> [u8; TAG_LEN] {
+        let mut aegis = self.begin_seal(label, in_out.len() as u64, None);
+        aegis.encrypt(in_out);
+        self.end_seal(aegis)
+    }
+
+    /// Begins a `Seal` or `Open` operation on a plaintext of the given length, returning the
+    /// AEGIS-128L instance to encrypt or decrypt it with.
+    ///
+    /// The length is always a `u64`, so the transcript is the same on every platform. If a
+    /// truncated tag length is given, it is mixed in before the key is derived.
+    #[inline]
+    fn begin_seal(&mut self, label: &str, len: u64, tag_len: Option<usize>) -> Aegis128L {
+        // Append an AuthCrypt op header with the label to the transcript.
+        //
+        //   0x05 || label || right_encode(|label|)
+        self.op_header(OpCode::AuthCrypt, label);
+
+        // Perform a Mix operation with the plaintext length.
+        self.mix_int("len", len * 8);
+
+        // Perform a Mix operation with the truncated tag length, if any.
+        if let Some(tag_len) = tag_len {
+            self.mix_int("tag-len", tag_len as u64 * 8);
+        }
+        self.bytes_crypted = self.bytes_crypted.saturating_add(len);
+
+        // Derive an AEGIS-128L key and nonce.
+        self.derive_aegis_128l("key")
+    }
+
+    /// Ends a `Seal` or `Open` operation begun with [`Protocol::begin_seal`], returning the
+    /// 128-bit tag.
+    #[inline]
+    fn end_seal(&mut self, aegis: Aegis128L) -> [u8; TAG_LEN] {
+        // Finalize the AEGIS-128L tags.
+        let (tag128, tag256) = aegis.finalize();
+
+        // Perform a Mix operation with the 256-bit AEGIS-128L tag.
+        self.mix("tag", &tag256);
+
+        tag128
+    }
+
+    /// Decrypts the given ciphertext in place as part of an `Open` operation and returns `true` if
+    /// the given 128-bit authentication tag is valid. If not, the plaintext is zeroed.
+    #[inline]
+    fn open_detached(&mut self, label: &str, in_out: &mut [u8], tag128_in: &[u8]) -> bool {
+        // Decrypt the ciphertext and calculate the counterfactual tag.
+        let mut aegis = self.begin_seal(label, in_out.len() as u64, None);
+        aegis.decrypt(in_out);
+        let tag128 = self.end_seal(aegis);
+
+        // Check the tag against the counterfactual tag in constant time.
+        if ct_eq(tag128_in, &tag128) {
+            // If the tag is verified, then the ciphertext is authentic.
+            true
+        } else {
+            // Otherwise, the ciphertext is inauthentic and we zero out the inauthentic plaintext to
+            // avoid bugs where the caller forgets to check the return value of this function and
+            // discloses inauthentic plaintext.
+            in_out.fill(0);
+            false
+        }
+    }
+
+    /// Derives an AEGIS-128L key and nonce from the protocol's current state.
+    #[inline]
+    fn derive_aegis_128l(&mut self, label: &str) -> Aegis128L {
+        let kn = self.derive_array::<{ aegis_128l::KEY_LEN + aegis_128l::NONCE_LEN }>(label);
+        let (k, n) = kn.split_at(aegis_128l::KEY_LEN);
+        Aegis128L::new(
+            k.try_into().expect("should be KEY_LEN bytes"),
+            n.try_into().expect("should be NONCE_LEN bytes"),
+        )
+    }
+
+    /// Performs a `DeriveCipher` operation, deriving an AEGIS-128L key and nonce for use outside of
+    /// the protocol transcript.
+    #[inline]
+    fn derive_cipher_op(&mut self, label: &str) -> Aegis128L {
+        let mut kn = [0u8; aegis_128l::KEY_LEN + aegis_128l::NONCE_LEN];
+        self.derive_reader(OpCode::DeriveCipher, label, kn.len()).1.read(&mut kn);
+        let (k, n) = kn.split_at(aegis_128l::KEY_LEN);
+        Aegis128L::new(
+            k.try_into().expect("should be KEY_LEN bytes"),
+            n.try_into().expect("should be NONCE_LEN bytes"),
+        )
+    }
+
+    /// Creates a protocol with an empty transcript.
+    #[inline]
+    fn empty() -> Protocol {
+        Protocol {
+            transcript: TurboShake128::from_core(TurboShake128Core::new(0x22)),
+            bytes_crypted: 0,
+        }
+    }
+
+    /// Ends a `Mix` operation whose input was appended to the transcript directly by appending the
+    /// right-encoded length of the input.
+    #[inline]
+    fn end_mix(&mut self, len: u64) {
+        self.transcript.update(right_encode(&mut [0u8; 9], len * 8));
+    }
+
+    /// Appends an operation header with an optional label to the protocol transcript.
+    #[inline]
+    fn op_header(&mut self, op_code: OpCode, label: &str) {
+        // Count the operation, if enabled.
+        #[cfg(feature = "metrics")]
+        metrics::record(op_code);
+
+        // Append the operation code and label to the transcript:
+        //
+        //   op_code || label || right_encode(|label|)
+        self.transcript.update(&[op_code as u8]);
+        self.transcript.update(label.as_bytes());
+        self.transcript.update(right_encode(&mut [0u8; 9], label.len() as u64 * 8));
+    }
+}
+
+/// An error returned by [`Protocol::try_derive`] or [`Protocol::derive_limited`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeriveError {
+    /// The output slice was empty.
+    EmptyOutput,
+    /// The output slice was longer than the allowed maximum.
+    TooLong,
+}
+
+impl core::fmt::Display for DeriveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DeriveError::EmptyOutput => write!(f, "derive output is empty"),
+            DeriveError::TooLong => write!(f, "derive output is too long"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DeriveError {}
+
+/// How the length prefix of a frame sealed with [`Protocol::seal_with_len_prefix`] is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LenPrefix {
+    /// The length prefix is authenticated and written in the clear, so a reader can determine the
+    /// length of the frame before opening it.
+    Clear,
+    /// The length prefix is encrypted and authenticated.
+    Encrypted,
+}
+
+/// A labeled field of a record sealed with [`Protocol::seal_record`].
+#[derive(Debug)]
+pub enum Field<'a> {
+    /// A field which is authenticated but not encrypted.
+    Clear(&'a str, &'a [u8]),
+    /// A field which is encrypted in place and authenticated.
+    Secret(&'a str, &'a mut [u8]),
+}
+
+/// All Lockstitch operation types.
+#[derive(Debug, Clone, Copy)]
+enum OpCode {
+    /// Initialize a protocol with a domain separation string.
+    Init = 0x01,
+    /// Mix a labeled input into the protocol transcript.
+    Mix = 0x02,
+    /// Derive a labeled output from the protocol transcript.
+    Derive = 0x03,
+    /// Encrypt or decrypt a labeled input using the protocol transcript as a key.
+    Crypt = 0x04,
+    /// Seal or open a labeled input using the protocol transcript as a key.
+    AuthCrypt = 0x05,
+    /// Send or receive a labeled MAC of the protocol transcript.
+    Mac = 0x06,
+    /// Derive a labeled AEGIS-128L key and nonce for use outside of the protocol transcript.
+    DeriveCipher = 0x07,
+}
+
+/// A [`std::io::Write`] implementation which combines all written data into a single `Mix`
+/// operation and passes all writes to an inner writer.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct MixWriter<W> {
+    protocol: Protocol,
+    inner: W,
+    len: u64,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> MixWriter<W> {
+    /// Finishes the `Mix` operation and returns the inner [`Protocol`] and writer.
+    #[inline]
+    pub fn into_inner(mut self) -> (Protocol, W) {
+        self.protocol.end_mix(self.len);
+        (self.protocol, self.inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> std::io::Write for MixWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Track the written length.
+        self.len += buf.len() as u64;
+        // Append the written slice to the protocol transcript.
+        self.protocol.transcript.update(buf);
+        // Pass the slice to the inner writer and return the result.
+        self.inner.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An opaque snapshot of a [`Protocol`], created by [`Protocol::checkpoint`].
+///
+/// **IMPORTANT:** A checkpoint is as sensitive as any secret mixed into the protocol.
+#[derive(Clone)]
+pub struct Checkpoint(Protocol);
+
+impl core::fmt::Debug for Checkpoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Checkpoint").finish_non_exhaustive()
+    }
+}
+
+/// A single `Mix` operation in progress, created by [`Protocol::mixer`].
+///
+/// All data passed to a `Mixer` via [`Extend`] or [`std::io::Write`] is appended to the operation's
+/// input. The operation ends, and the input's length is appended to the transcript, when the
+/// `Mixer` is dropped or [`Mixer::finish`] is called. [`MixWriter`] is the owned equivalent which
+/// also passes data to an inner writer.
+#[derive(Debug)]
+pub struct Mixer<'a> {
+    protocol: &'a mut Protocol,
+    len: u64,
+}
+
+impl Mixer<'_> {
+    /// Finishes the `Mix` operation. This is equivalent to dropping the `Mixer`.
+    #[inline]
+    pub fn finish(self) {}
+
+    /// Appends the given slice to the operation's input.
+    #[inline]
+    fn update(&mut self, input: &[u8]) {
+        self.protocol.transcript.update(input);
+        self.len += input.len() as u64;
+    }
+}
+
+impl Drop for Mixer<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.protocol.end_mix(self.len);
+    }
+}
+
+impl Extend<u8> for Mixer<'_> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        // Append the input to the transcript in batches.
+        let mut batch = [0u8; 64];
+        let mut iter = iter.into_iter().peekable();
+        while iter.peek().is_some() {
+            let mut n = 0;
+            for (b, x) in batch.iter_mut().zip(iter.by_ref()) {
+                *b = x;
+                n += 1;
+            }
+            self.update(&batch[..n]);
+        }
+    }
+}
+
+impl<'b> Extend<&'b u8> for Mixer<'_> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = &'b u8>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for Mixer<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens the given mutable slice in place with `old` and, if it was authenticated, seals the
+/// plaintext in place with `new`, e.g. to rotate the key protecting data at rest.
+///
+/// This is exactly equivalent to calling [`Protocol::open`] on `old` followed by
+/// [`Protocol::seal`] on `new`, both with the given label. The plaintext never leaves `in_out`. If
+/// the input is not authenticated, returns `false` and fills `in_out` with zeros without modifying
+/// `new`. If `in_out` is shorter than [`TAG_LEN`], returns `false` without modifying either
+/// protocol.
+#[must_use]
+pub fn reencrypt(old: &mut Protocol, new: &mut Protocol, label: &str, in_out: &mut [u8]) -> bool {
+    if old.open(label, in_out).is_none() {
+        in_out.fill(0);
+        return false;
+    }
+    new.seal(label, in_out);
+    true
+}
+
+/// Compares two slices for equality in constant time.
+#[inline]
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut res = 1;
+    a.cmovne(b, 0, &mut res);
+    res != 0
+}
+
+/// Returns a copy of `b` if `cond` is `true` and a copy of `a` otherwise, in constant time.
+#[inline]
+pub fn ct_select<const N: usize>(a: &[u8; N], b: &[u8; N], cond: bool) -> [u8; N] {
+    let mut out = *a;
+    for (o, b) in out.iter_mut().zip(b) {
+        o.cmovnz(b, u8::from(cond));
+    }
+    out
+}
+
+/// Calculates a keyed 64-bit hash of the given data, e.g. for hash tables which must resist
+/// hash-flooding attacks.
+///
+/// This is a fast MAC which is entirely distinct from [`Protocol`]: it does not use a transcript or
+/// TurboSHAKE128. Instead, it processes `data` as associated data with a single AEGIS-128L instance
+/// using `key` and an all-zero nonce and returns the first 64 bits of the 128-bit tag. With a
+/// uniformly random key, it is suitable where SipHash would be used, but its 64-bit output is not
+/// suitable as a general-purpose MAC.
+///
+/// AEGIS-128L's initialization and finalization give `short_hash` a higher fixed cost than SipHash,
+/// so it is slower for very short inputs and only catches up as inputs approach a few hundred
+/// bytes. Compare the `short_hash` and `siphash` benchmarks on the target hardware before choosing
+/// it for performance.
+#[inline]
+pub fn short_hash(key: &[u8; aegis_128l::KEY_LEN], data: &[u8]) -> u64 {
+    let mut aegis = Aegis128L::new(key, &[0u8; aegis_128l::NONCE_LEN]);
+    aegis.ad(data);
+    let (tag128, _) = aegis.finalize();
+    u64::from_le_bytes(tag128[..8].try_into().expect("should be 8 bytes"))
+}
+
+/// Encodes a file's size and modification time as `u64_be(size) || i64_be(secs) || u32_be(nanos)`.
+#[cfg(feature = "std")]
+fn encode_metadata(len: u64, modified: std::time::SystemTime) -> [u8; 20] {
+    let (secs, nanos) = match modified.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            // Round timestamps before the epoch towards negative infinity.
+            let d = e.duration();
+            match d.subsec_nanos() {
+                0 => (-(d.as_secs() as i64), 0),
+                n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n),
+            }
+        }
+    };
+    let mut out = [0u8; 20];
+    out[..8].copy_from_slice(&len.to_be_bytes());
+    out[8..16].copy_from_slice(&secs.to_be_bytes());
+    out[16..].copy_from_slice(&nanos.to_be_bytes());
+    out
+}
+
+/// Encodes a value using [NIST SP 800-185][]'s `right_encode`.
+///
+/// [NIST SP 800-185]: https://www.nist.gov/publications/sha-3-derived-functions-cshake-kmac-tuplehash-and-parallelhash
+#[inline]
+fn right_encode(buf: &mut [u8; 9], value: u64) -> &[u8] {
+    // Write the value as 8 big-endian bytes followed by the number of significant bytes, which is
+    // always in 1..=8. Destructuring and saturating arithmetic keep this free of panic branches.
+    let [b0, b1, b2, b3, b4, b5, b6, b7] = value.to_be_bytes();
+    let n = 8usize.saturating_sub(value.leading_zeros() as usize / 8).max(1);
+    *buf = [b0, b1, b2, b3, b4, b5, b6, b7, n as u8];
+
+    // Return the significant bytes and the length byte.
+    let (_, encoded) = buf.split_at(8usize.saturating_sub(n));
+    encoded
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::io::{self, Cursor, Write};
+
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn known_answers() {
+        let mut protocol = Protocol::new("com.example.kat");
+        protocol.mix("first", b"one");
+        protocol.mix("second", b"two");
+
+        expect!["9d741fc2d9c5cba0"].assert_eq(&hex::encode(protocol.derive_array::<8>("third")));
+
+        let mut plaintext = b"this is an example".to_vec();
+        protocol.encrypt("fourth", &mut plaintext);
+        expect!["ec324ce127e09da0b60bf87199acd016969a"].assert_eq(&hex::encode(plaintext));
+
+        let plaintext = b"this is an example";
+        let mut sealed = vec![0u8; plaintext.len() + TAG_LEN];
+        sealed[..plaintext.len()].copy_from_slice(plaintext);
+        protocol.seal("fifth", &mut sealed);
+
+        expect!["9aec57dd29ad1dfd45ca56098e26bdbb928d39e23c9bf64a712a9d04adfab8803707"]
+            .assert_eq(&hex::encode(sealed));
+
+        expect!["21d58fc6560a5c49"].assert_eq(&hex::encode(protocol.derive_array::<8>("sixth")));
+    }
+
+    #[test]
+    fn seal_and_derive_known_answers() {
+        let mut protocol = Protocol::new("com.example.kat");
+        protocol.mix("first", b"one");
+
+        let mut sealed = b"this is an example".to_vec();
+        sealed.extend_from_slice(&[0u8; TAG_LEN]);
+        let id = protocol.seal_and_derive::<16>("second", &mut sealed);
+
+        expect!["8e1d06faa59e4b483c0118522628f64d907e93054e90dcd282c2f0446496b21a636c"]
+            .assert_eq(&hex::encode(sealed));
+        expect!["15750db43f5755a88583e7711b4ff4a2"].assert_eq(&hex::encode(id));
+
+        let mut separate = Protocol::new("com.example.kat");
+        separate.mix("first", b"one");
+        let mut sealed = b"this is an example".to_vec();
+        sealed.extend_from_slice(&[0u8; TAG_LEN]);
+        separate.seal("second", &mut sealed);
+        assert_eq!(id, separate.derive_array::<16>("second"));
+    }
+
+    #[test]
+    fn readers() {
+        let mut slices = Protocol::new("com.example.streams");
+        slices.mix("first", b"one");
+        slices.mix("second", b"two");
+
+        let streams = Protocol::new("com.example.streams");
+        let mut streams_write = streams.mix_writer("first", io::sink());
+        io::copy(&mut Cursor::new(b"one"), &mut streams_write)
+            .expect("cursor reads and sink writes should be infallible");
+        let (streams, _) = streams_write.into_inner();
+
+        let mut output = Vec::new();
+        let mut streams_write = streams.mix_writer("second", &mut output);
+        io::copy(&mut Cursor::new(b"two"), &mut streams_write)
+            .expect("cursor reads and sink writes should be infallible");
+        let (mut streams, output) = streams_write.into_inner();
+
+        assert_eq!(slices.derive_array::<16>("third"), streams.derive_array::<16>("third"));
+        assert_eq!(b"two".as_slice(), output);
+    }
+
+    #[test]
+    fn writer_chunk_sizes() {
+        let data = (0..=255u8).cycle().take(10_000).collect::<Vec<u8>>();
+
+        let mut slice = Protocol::new("com.example.chunks");
+        slice.mix("data", &data);
+        let expected = slice.derive_array::<16>("output");
+
+        for chunk_size in [1, 7, 64, 512, 4096, 65536] {
+            let protocol = Protocol::new("com.example.chunks");
+            let mut writer = protocol.mix_writer("data", io::sink());
+            for chunk in data.chunks(chunk_size) {
+                writer.write_all(chunk).expect("sink writes should be infallible");
+            }
+            let (mut protocol, _) = writer.into_inner();
+
+            assert_eq!(
+                expected,
+                protocol.derive_array::<16>("output"),
+                "chunk size of {chunk_size} should not change the protocol's state"
+            );
+        }
+    }
+
+    #[test]
+    fn unkeyed() {
+        let mut a = Protocol::unkeyed();
+        let mut b = Protocol::new("");
+        assert_eq!(a.derive_array::<16>("state"), b.derive_array::<16>("state"));
+        assert_ne!(
+            Protocol::unkeyed().derive_array::<16>("state"),
+            Protocol::new("com.example.unkeyed").derive_array::<16>("state")
+        );
+    }
+
+    #[test]
+    fn edge_case() {
+        let mut sender = Protocol::new("");
+        let mut message = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        sender.encrypt("message", &mut message);
+        let tag_s = sender.derive_array::<TAG_LEN>("tag");
+
+        let mut receiver = Protocol::new("");
+        receiver.decrypt("message", &mut message);
+        let tag_r = receiver.derive_array::<TAG_LEN>("tag");
+
+        assert_eq!(message, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(tag_s, tag_r);
+    }
+
+    #[test]
+    fn op_codes_separate_outputs() {
+        let mut protocol = Protocol::new("com.example.op-codes");
+        protocol.mix("key", b"a secret key");
+
+        // Derive output and encrypt a block of zeros, revealing the keystream, from identical
+        // protocol states with identical labels.
+        let derived = protocol.clone().derive_array::<64>("output");
+
+        let mut crypt_keystream = [0u8; 64];
+        protocol.clone().encrypt("output", &mut crypt_keystream);
+
+        let mut auth_crypt_keystream = [0u8; 64 + TAG_LEN];
+        protocol.clone().seal("output", &mut auth_crypt_keystream);
+
+        assert_ne!(derived, crypt_keystream, "Derive and Crypt outputs must be independent");
+        assert_ne!(
+            derived,
+            auth_crypt_keystream[..64],
+            "Derive and AuthCrypt outputs must be independent"
+        );
+        assert_ne!(
+            crypt_keystream,
+            auth_crypt_keystream[..64],
+            "Crypt and AuthCrypt outputs must be independent"
+        );
+    }
+
+    #[test]
+    fn mix_padded() {
+        for (input, padded_len) in [(&b""[..], 0), (b"", 32), (b"secret", 32), (b"secret", 200)] {
+            let mut encoded = input.to_vec();
+            encoded.resize(padded_len, 0);
+            encoded.extend_from_slice(&(input.len() as u64).to_be_bytes());
+
+            let mut expected = Protocol::new("com.example.padded");
+            expected.mix("password", &encoded);
+
+            let mut actual = Protocol::new("com.example.padded");
+            actual.mix_padded("password", input, padded_len);
+
+            assert_eq!(expected.derive_array::<16>("state"), actual.derive_array::<16>("state"));
+        }
+
+        let mut a = Protocol::new("com.example.padded");
+        a.mix_padded("password", b"secret", 32);
+        let mut b = Protocol::new("com.example.padded");
+        b.mix_padded("password", b"secret\0", 32);
+        assert_ne!(a.derive_array::<16>("state"), b.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn mix_iter() {
+        for len in [0, 1, 63, 64, 65, 1000] {
+            let input = (0..len).map(|i| i as u8).collect::<Vec<u8>>();
+
+            let mut expected = Protocol::new("com.example.iter");
+            expected.mix("input", &input);
+
+            let mut actual = Protocol::new("com.example.iter");
+            actual.mix_iter("input", (0..len).map(|i| i as u8));
+
+            assert_eq!(
+                expected.derive_array::<16>("state"),
+                actual.derive_array::<16>("state"),
+                "len = {len}"
+            );
+        }
+    }
+
+    #[test]
+    fn mix_str() {
+        let mut protocol = Protocol::new("com.example.str");
+        protocol.mix_str("first", "ab");
+        protocol.mix_str("second", "c");
+        expect!["a44257348820d13b"]
+            .assert_eq(&hex::encode(protocol.clone().derive_array::<8>("out")));
+
+        let mut other = Protocol::new("com.example.str");
+        other.mix_str("first", "a");
+        other.mix_str("second", "bc");
+        assert_ne!(protocol.clone().derive_array::<8>("out"), other.derive_array::<8>("out"));
+
+        let mut bytes = Protocol::new("com.example.str");
+        bytes.mix("first", b"ab");
+        bytes.mix("second", b"c");
+        assert_eq!(protocol.derive_array::<8>("out"), bytes.derive_array::<8>("out"));
+    }
+
+    #[test]
+    fn empty_derive() {
+        let mut protocol = Protocol::new("com.example.empty");
+        protocol.mix("key", b"a secret key");
+
+        let mut unchanged = protocol.clone();
+        assert_eq!(Err(DeriveError::EmptyOutput), unchanged.try_derive("output", &mut []));
+        assert_eq!(
+            protocol.clone().derive_array::<16>("state"),
+            unchanged.derive_array::<16>("state")
+        );
+
+        // An empty derive ratchets the protocol.
+        let mut ratcheted = protocol.clone();
+        ratcheted.ratchet("output");
+        protocol.derive("output", &mut []);
+        assert_eq!(
+            protocol.clone().derive_array::<16>("state"),
+            ratcheted.derive_array::<16>("state")
+        );
+        expect!["791d6a5fee759991"].assert_eq(&hex::encode(protocol.derive_array::<8>("state")));
+
+        let mut out = [0u8; 8];
+        assert_eq!(Ok(()), Protocol::new("com.example.empty").try_derive("output", &mut out));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut protocol = Protocol::new("com.example.save");
+        protocol.mix("key", b"a secret key");
+        protocol.encrypt("message", &mut [0u8; 10]);
+
+        let mut saved = Vec::new();
+        protocol.save(&mut saved).expect("saving to a Vec should be infallible");
+        assert_eq!(4 + 1 + KDK_LEN + 8, saved.len());
+
+        let mut loaded = Protocol::load(saved.as_slice()).expect("should load");
+        assert_eq!(10, loaded.bytes_crypted());
+        assert_eq!(protocol.derive_array::<16>("state"), loaded.derive_array::<16>("state"));
+
+        for len in 0..saved.len() {
+            let err = Protocol::load(&saved[..len]).expect_err("should reject truncated state");
+            assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+        }
+
+        let mut wrong_magic = saved.clone();
+        wrong_magic[0] ^= 1;
+        let err = Protocol::load(wrong_magic.as_slice()).expect_err("should reject wrong magic");
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+        let mut wrong_version = saved.clone();
+        wrong_version[4] = 2;
+        let err = Protocol::load(wrong_version.as_slice()).expect_err("should reject version");
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn derive_limited() {
+        let protocol = Protocol::new("com.example.limited");
+
+        let mut unchanged = protocol.clone();
+        assert_eq!(
+            Err(DeriveError::TooLong),
+            unchanged.derive_limited("output", &mut [0u8; 33], 32)
+        );
+        assert_eq!(
+            protocol.clone().derive_array::<16>("state"),
+            unchanged.derive_array::<16>("state")
+        );
+
+        let mut out = [0u8; 32];
+        assert_eq!(Ok(()), protocol.clone().derive_limited("output", &mut out, 32));
+        assert_eq!(protocol.clone().derive_array::<32>("output"), out);
+    }
+
+    #[test]
+    fn ratchet_n() {
+        let mut protocol = Protocol::new("com.example.ratchet");
+        protocol.mix("key", b"a secret key");
+
+        for k in [0, 1, 2, 10] {
+            let mut skipped = protocol.clone();
+            skipped.ratchet_n("ratchet", k);
+
+            let mut stepped = protocol.clone();
+            for _ in 0..k {
+                stepped.ratchet("ratchet");
+            }
+
+            assert_eq!(skipped.derive_array::<16>("state"), stepped.derive_array::<16>("state"));
+        }
+
+        protocol.ratchet_n("ratchet", 3);
+        expect!["c02f7cc7484f7898"].assert_eq(&hex::encode(protocol.derive_array::<8>("state")));
+    }
+
+    #[test]
+    fn derive_to_digest() {
+        let mut protocol = Protocol::new("com.example.digest");
+        protocol.mix("key", b"a secret key");
+
+        for n in [0, 1, 167, 168, 169, 1000] {
+            let mut expected = protocol.clone();
+            let mut out = vec![0u8; n];
+            expected.derive("output", &mut out);
+
+            let mut actual = protocol.clone();
+            let mut digest = Vec::new();
+            actual.derive_to_digest("output", &mut DigestBuffer(&mut digest), n);
+
+            assert_eq!(out, digest, "n = {n}");
+            assert_eq!(expected.derive_array::<16>("state"), actual.derive_array::<16>("state"));
+        }
+    }
+
+    struct DigestBuffer<'a>(&'a mut Vec<u8>);
+
+    impl Update for DigestBuffer<'_> {
+        fn update(&mut self, data: &[u8]) {
+            self.0.extend_from_slice(data);
+        }
+    }
+
+    #[test]
+    fn derive_cipher() {
+        let mut protocol = Protocol::new("com.example.cipher");
+        protocol.mix("key", b"a secret key");
+        let mut expected = protocol.clone();
+
+        let keystream = |context: &[u8]| {
+            let mut block = [0u8; 64];
+            let mut cipher = protocol.derive_cipher("record", context);
+            cipher.encrypt(&mut block);
+            block
+        };
+
+        assert_eq!(keystream(b"record 1"), keystream(b"record 1"));
+        assert_ne!(keystream(b"record 1"), keystream(b"record 2"));
+
+        // The cipher is the one derive_aegis returns after the same Mix.
+        let mut clone = protocol.clone();
+        clone.mix("record", b"record 1");
+        let mut block = [0u8; 64];
+        clone.derive_aegis("key").encrypt(&mut block);
+        assert_eq!(keystream(b"record 1"), block);
+
+        // Deriving ciphers doesn't modify the protocol.
+        assert_eq!(expected.derive_array::<16>("state"), protocol.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn open_short_input() {
+        let mut protocol = Protocol::new("com.example.short");
+        protocol.mix("key", b"a secret key");
+        let expected = protocol.clone();
+
+        for len in 0..TAG_LEN {
+            let mut in_out = vec![0u8; len];
+            assert_eq!(None, protocol.open("message", &mut in_out));
+        }
+
+        // Rejecting short inputs doesn't modify the protocol.
+        assert_eq!(
+            expected.clone().derive_array::<16>("state"),
+            protocol.clone().derive_array::<16>("state")
+        );
+
+        // An empty sealed message is exactly TAG_LEN bytes long.
+        let mut in_out = [0u8; TAG_LEN];
+        expected.clone().seal("message", &mut in_out);
+        assert_eq!(Some([].as_slice()), protocol.open("message", &mut in_out));
+    }
+
+    #[test]
+    fn len_prefix() {
+        let mut protocol = Protocol::new("com.example.frame");
+        protocol.mix("key", b"a secret key");
+
+        for mode in [LenPrefix::Clear, LenPrefix::Encrypted] {
+            let mut frame = vec![0u8; 8];
+            frame.extend_from_slice(b"a message");
+            frame.extend_from_slice(&[0u8; TAG_LEN]);
+            protocol.clone().seal_with_len_prefix("message", mode, &mut frame);
+            assert_eq!(8 + 9 + TAG_LEN, frame.len());
+            if mode == LenPrefix::Clear {
+                assert_eq!(9u64.to_be_bytes(), frame[..8]);
+            } else {
+                assert_ne!(9u64.to_be_bytes(), frame[..8]);
+            }
+
+            let mut opened = frame.clone();
+            assert_eq!(
+                Some(b"a message".as_slice()),
+                protocol.clone().open_with_len_prefix("message", mode, &mut opened)
+            );
+
+            let mut tampered = frame.clone();
+            tampered[7] ^= 1;
+            assert_eq!(None, protocol.clone().open_with_len_prefix("message", mode, &mut tampered));
+
+            assert_eq!(
+                None,
+                protocol.clone().open_with_len_prefix(
+                    "message",
+                    mode,
+                    &mut frame[..8 + TAG_LEN - 1]
+                )
+            );
+        }
+
+        // An authenticated frame with a mismatched length prefix is rejected and zeroed.
+        let mut sender = protocol.clone();
+        let mut frame = vec![0u8; 8 + 9 + TAG_LEN];
+        frame[..8].copy_from_slice(&5u64.to_be_bytes());
+        frame[8..17].copy_from_slice(b"a message");
+        sender.mix("len-prefix", &5u64.to_be_bytes());
+        sender.seal("message", &mut frame[8..]);
+        assert_eq!(
+            None,
+            protocol.clone().open_with_len_prefix("message", LenPrefix::Clear, &mut frame)
+        );
+        assert_eq!([0u8; 9], frame[8..17]);
+
+        let mut frame = vec![0u8; 8 + 9 + TAG_LEN];
+        frame[8..17].copy_from_slice(b"a message");
+        protocol.clone().seal_with_len_prefix("message", LenPrefix::Encrypted, &mut frame);
+        expect!["0da9879c8facb56d79e3ea482e43bf28293f0e232a9aba621b1805d96d4dab75c8"]
+            .assert_eq(&hex::encode(frame));
+    }
+
+    #[test]
+    fn siv() {
+        let mut protocol = Protocol::new("com.example.siv");
+        protocol.mix("key", b"a secret key");
+
+        let seal = |plaintext: &[u8]| {
+            let mut in_out = plaintext.to_vec();
+            in_out.extend_from_slice(&[0u8; TAG_LEN]);
+            protocol.clone().seal_siv("message", &mut in_out);
+            in_out
+        };
+
+        let sealed = seal(b"a message");
+        expect!["885cbe9fd228d2b04822953fe314faa835e9a04c057001bdb9"]
+            .assert_eq(&hex::encode(&sealed));
+        assert_eq!(sealed, seal(b"a message"));
+        assert_ne!(sealed, seal(b"a massage"));
+
+        let mut receiver = protocol.clone();
+        let mut in_out = sealed.clone();
+        assert_eq!(Some(b"a message".as_slice()), receiver.open_siv("message", &mut in_out));
+
+        for i in 0..sealed.len() {
+            let mut in_out = sealed.clone();
+            in_out[i] ^= 1;
+            assert_eq!(None, protocol.clone().open_siv("message", &mut in_out));
+        }
+
+        assert_eq!(None, protocol.clone().open_siv("message", &mut [0u8; TAG_LEN - 1]));
+
+        let mut sender = protocol.clone();
+        let mut in_out = b"a message".to_vec();
+        in_out.extend_from_slice(&[0u8; TAG_LEN]);
+        sender.seal_siv("message", &mut in_out);
+        assert_eq!(sealed, in_out);
+        assert_eq!(sender.derive_array::<16>("state"), receiver.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn records() {
+        let mut protocol = Protocol::new("com.example.record");
+        protocol.mix("key", b"a secret key");
+
+        let mut body = *b"a secret body";
+        let tag = protocol.clone().seal_record(
+            "record",
+            &mut [
+                Field::Clear("header", b"a header"),
+                Field::Secret("body", &mut body),
+                Field::Clear("trailer", b"a trailer"),
+            ],
+        );
+        assert_ne!(b"a secret body", &body);
+
+        let mut sealed = body;
+        assert!(protocol.clone().open_record(
+            "record",
+            &mut [
+                Field::Clear("header", b"a header"),
+                Field::Secret("body", &mut sealed),
+                Field::Clear("trailer", b"a trailer"),
+            ],
+            &tag,
+        ));
+        assert_eq!(b"a secret body", &sealed);
+
+        let mut sealed = body;
+        assert!(!protocol.clone().open_record(
+            "record",
+            &mut [
+                Field::Clear("header", b"a header"),
+                Field::Secret("body", &mut sealed),
+                Field::Clear("trailer", b"another trailer"),
+            ],
+            &tag,
+        ));
+        assert_eq!([0u8; 13], sealed);
+    }
+
+    #[test]
+    fn debug_is_opaque() {
+        let mut protocol = Protocol::new("com.example.debug");
+        protocol.mix("key", b"a secret key");
+
+        let debug = format!("{protocol:?} {protocol:#?}");
+        assert_eq!("Protocol { .. } Protocol { .. }", debug);
+        assert!(!debug.contains("a secret key"));
+        assert!(!debug.contains(&hex::encode(b"a secret key")));
+    }
+
+    #[test]
+    fn bytes_crypted() {
+        let mut protocol = Protocol::new("com.example.count");
+        assert_eq!(0, protocol.bytes_crypted());
+
+        protocol.mix("key", b"a secret key");
+        protocol.derive("output", &mut [0u8; 32]);
+        assert_eq!(0, protocol.bytes_crypted());
+
+        protocol.encrypt("message", &mut [0u8; 10]);
+        protocol.decrypt("message", &mut [0u8; 20]);
+        assert_eq!(30, protocol.bytes_crypted());
+
+        let mut receiver = protocol.clone();
+        let mut sealed = [0u8; 5 + TAG_LEN];
+        protocol.seal("message", &mut sealed);
+        assert_eq!(35, protocol.bytes_crypted());
+
+        assert!(receiver.open("message", &mut sealed).is_some());
+        assert_eq!(35, receiver.bytes_crypted());
+    }
+
+    #[test]
+    fn verify_clone() {
+        let mut sender = Protocol::new("com.example.verify");
+        sender.mix("key", b"a secret key");
+        let mut receiver = sender.clone();
+
+        let mut message = b"a message".to_vec();
+        message.extend_from_slice(&[0u8; TAG_LEN]);
+        sender.seal("message", &mut message);
+
+        let mut forgery = message.clone();
+        forgery[0] ^= 1;
+
+        let mut accepted = None;
+        for mut candidate in [forgery, message] {
+            let mut verifier = receiver.verify_clone();
+            if verifier.open("message", &mut candidate).is_some() {
+                accepted = Some(candidate);
+                receiver = verifier;
+                break;
+            }
+        }
+
+        assert_eq!(Some(b"a message".as_slice()), accepted.as_deref().map(|m| &m[..9]));
+        assert_eq!(sender.derive_array::<16>("state"), receiver.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn derive_id() {
+        let mut protocol = Protocol::new("com.example.id");
+        protocol.mix("name", b"a stable name");
+
+        let id = protocol.clone().derive_id("id");
+        let raw = protocol.clone().derive_array::<16>("id");
+        assert_eq!(0x40, id[6] & 0xf0);
+        assert_eq!(0x80, id[8] & 0xc0);
+        assert_eq!(raw[6] & 0x0f, id[6] & 0x0f);
+        assert_eq!(raw[8] & 0x3f, id[8] & 0x3f);
+        assert_eq!((&raw[..6], &raw[7..8], &raw[9..]), (&id[..6], &id[7..8], &id[9..]));
+
+        expect!["2db8f9a1ed57467a886144e17f2ed149"].assert_eq(&hex::encode(id));
+    }
+
+    #[test]
+    fn acknowledgements() {
+        let mut client = Protocol::new("com.example.ack");
+        client.mix("request", b"a request");
+        let mut server = client.clone();
+
+        let ack = server.acknowledge::<32>("ack");
+        let mut forged = ack;
+        forged[31] ^= 1;
+
+        assert!(!client.clone().verify_acknowledgement("ack", &forged));
+        assert!(!client.clone().verify_acknowledgement("ack", &ack[..16]));
+        assert!(client.verify_acknowledgement("ack", &ack));
+        assert_eq!(client.derive_array::<16>("state"), server.derive_array::<16>("state"));
+
+        let mut long = Protocol::new("com.example.ack");
+        let ack = long.clone().acknowledge::<500>("ack");
+        assert!(long.clone().verify_acknowledgement("ack", &ack));
+        let mut forged = ack;
+        forged[400] ^= 1;
+        assert!(!long.verify_acknowledgement("ack", &forged));
+    }
+
+    #[test]
+    fn metadata_encoding() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        expect!["0000000000000400000000006553f100075bcd15"].assert_eq(&hex::encode(
+            encode_metadata(1024, UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789)),
+        ));
+        expect!["0000000000000000fffffffffffffffe1dcd6500"]
+            .assert_eq(&hex::encode(encode_metadata(0, UNIX_EPOCH - Duration::from_millis(1_500))));
+        expect!["ffffffffffffffff000000000000000000000000"]
+            .assert_eq(&hex::encode(encode_metadata(u64::MAX, UNIX_EPOCH)));
+    }
+
+    #[test]
+    fn mix_metadata() {
+        let meta = std::fs::metadata("Cargo.toml").expect("should read metadata");
+        let encoded = encode_metadata(meta.len(), meta.modified().expect("should have an mtime"));
+
+        let mut a = Protocol::new("com.example.metadata");
+        a.mix_metadata("file", &meta).expect("should mix metadata");
+        let mut b = Protocol::new("com.example.metadata");
+        b.mix("file", &encoded);
+        assert_eq!(a.derive_array::<16>("state"), b.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn truncated_tags() {
+        let mut sender = Protocol::new("com.example.truncated");
+        sender.mix("key", b"a secret key");
+        let receiver = sender.clone();
+
+        let mut full = *b"a message\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        sender.clone().seal("message", &mut full);
+
+        for tag_len in [1, 4, 8, TAG_LEN] {
+            let mut sealed = b"a message".to_vec();
+            sealed.resize(9 + tag_len, 0);
+            sender.clone().seal_with_tag_len("message", &mut sealed, tag_len);
+            assert_ne!(&sealed[9..], &full[9..9 + tag_len], "tag_len = {tag_len}");
+
+            assert_eq!(
+                receiver.clone().open_with_tag_len("message", &mut sealed.clone(), tag_len),
+                Some(b"a message".as_slice()),
+                "tag_len = {tag_len}"
+            );
+
+            let mut forged = sealed.clone();
+            forged[0] ^= 1;
+            assert_eq!(receiver.clone().open_with_tag_len("message", &mut forged, tag_len), None);
+            assert_eq!(&forged[..9], &[0u8; 9]);
+
+            if tag_len > 1 {
+                let mut wrong_len = sealed.clone();
+                assert_eq!(
+                    receiver.clone().open_with_tag_len("message", &mut wrong_len, tag_len - 1),
+                    None
+                );
+            }
+        }
+
+        let mut short = [0u8; 3];
+        let mut protocol = receiver.clone();
+        assert_eq!(protocol.open_with_tag_len("message", &mut short, 4), None);
+        assert_eq!(
+            protocol.derive_array::<16>("state"),
+            receiver.clone().derive_array::<16>("state")
+        );
+    }
+
+    #[test]
+    fn seal_full() {
+        let mut protocol = Protocol::new("com.example.iot");
+        protocol.mix("key", b"a secret key");
+        protocol.mix("nonce", b"a nonce");
+
+        let mut sealed = *b"reading: 22C\0\0\0\0";
+        protocol.clone().seal_full("message", b"sensor-7", &mut sealed, 4);
+        expect!["adf18d194a2a15c1c63150e3c8785be0"].assert_eq(&hex::encode(sealed));
+
+        let mut expected = *b"reading: 22C\0\0\0\0";
+        let mut p = protocol.clone();
+        p.mix("ad", b"sensor-7");
+        p.seal_with_tag_len("message", &mut expected, 4);
+        assert_eq!(expected, sealed);
+
+        assert_eq!(
+            protocol.clone().open_full("message", b"sensor-7", &mut sealed.clone(), 4),
+            Some(b"reading: 22C".as_slice())
+        );
+        assert_eq!(
+            protocol.clone().open_full("message", b"sensor-8", &mut sealed.clone(), 4),
+            None
+        );
+        assert_eq!(protocol.open_full("message", b"sensor-7", &mut sealed.clone(), 3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "tag length must be in 1..=TAG_LEN")]
+    fn overlong_tags() {
+        Protocol::new("com.example.truncated").seal_with_tag_len("message", &mut [0u8; 32], 17);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn mix_parallel() {
+        let data = (0..100_000).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut protocol = Protocol::new("com.example.parallel");
+        protocol.mix("key", b"a secret key");
+
+        let parallel = |threads: usize, segments: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("should build a thread pool");
+            let mut protocol = protocol.clone();
+            pool.install(|| protocol.mix_parallel("data", &data, segments));
+            protocol.derive_array::<16>("state")
+        };
+
+        for segments in [1, 3, 8, 1_000] {
+            let mut expected = protocol.clone();
+            expected.mix_int("segments", segments as u64);
+            let fingerprints = data
+                .chunks(data.len().div_ceil(segments))
+                .enumerate()
+                .flat_map(|(i, segment)| {
+                    let mut fork = expected.clone();
+                    fork.mix_int("segment", i as u64);
+                    fork.mix("data", segment);
+                    fork.derive_array::<32>("data")
+                })
+                .collect::<Vec<u8>>();
+            expected.mix("data", &fingerprints);
+            let expected = expected.derive_array::<16>("state");
+
+            assert_eq!(expected, parallel(1, segments), "segments = {segments}");
+            assert_eq!(expected, parallel(4, segments), "segments = {segments}");
+        }
+
+        assert_ne!(parallel(4, 3), parallel(4, 8));
+
+        // 9 bytes with 4 segments produces three 3-byte segments.
+        let mut short = protocol.clone();
+        short.mix_parallel("data", &data[..9], 4);
+        let mut expected = protocol.clone();
+        expected.mix_int("segments", 4);
+        let fingerprints = data[..9]
+            .chunks(3)
+            .enumerate()
+            .flat_map(|(i, segment)| {
+                let mut fork = expected.clone();
+                fork.mix_int("segment", i as u64);
+                fork.mix("data", segment);
+                fork.derive_array::<32>("data")
+            })
+            .collect::<Vec<u8>>();
+        expected.mix("data", &fingerprints);
+        assert_eq!(expected.derive_array::<16>("state"), short.derive_array::<16>("state"));
+
+        // Empty data produces no segments.
+        let mut empty = protocol.clone();
+        empty.mix_parallel("data", &[], 4);
+        let mut expected = protocol.clone();
+        expected.mix_int("segments", 4);
+        expected.mix("data", &[]);
+        let empty = empty.derive_array::<16>("state");
+        assert_eq!(expected.derive_array::<16>("state"), empty);
+        assert_ne!(empty, parallel(4, 4));
+    }
+
+    #[test]
+    fn timestamps() {
+        let state = |unix_secs, granularity| {
+            let mut protocol = Protocol::new("com.example.timestamp");
+            protocol.mix_timestamp(unix_secs, granularity);
+            protocol.derive_array::<16>("state")
+        };
+
+        assert_eq!(state(1_700_000_040, 60), state(1_700_000_099, 60));
+        assert_ne!(state(1_700_000_099, 60), state(1_700_000_100, 60));
+        assert_ne!(state(1_700_000_039, 60), state(1_700_000_040, 60));
+        assert_ne!(state(0, 60), state(0, 30));
+    }
+
+    #[test]
+    #[cfg(feature = "generic-array")]
+    fn derive_generic_array() {
+        use generic_array::typenum::U48;
+
+        let mut protocol = Protocol::new("com.example.generic-array");
+        protocol.mix("key", b"a secret key");
+
+        let expected = protocol.clone().derive_array::<48>("key");
+        let out = protocol.derive_generic_array::<U48>("key");
+        assert_eq!(expected.as_slice(), out.as_slice());
+    }
+
+    #[test]
+    fn derive_keys() {
+        let mut protocol = Protocol::new("com.example.keys");
+        protocol.mix("key", b"a secret key");
+
+        let [enc, mac, iv] = protocol.clone().derive_keys::<16, 3>("session");
+        let expected = protocol.clone().derive_array::<48>("session");
+        assert_eq!([enc, mac, iv].concat(), expected);
+        expect!["6e62a39c5419f360f2b9df89d20825d6"].assert_eq(&hex::encode(enc));
+
+        let mut a = protocol.clone();
+        let mut b = protocol;
+        a.derive_keys::<16, 3>("session");
+        b.derive_array::<48>("session");
+        assert_eq!(a.derive_array::<16>("state"), b.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn dyn_streams() {
+        let data = vec![9u8; 10_000];
+        let mut expected = Protocol::new("com.example.dyn");
+        expected.mix("data", &data);
+        let expected = expected.derive_array::<16>("state");
+
+        let mut reader: Box<dyn io::Read> = Box::new(Cursor::new(data.clone()));
+        let mut protocol = Protocol::new("com.example.dyn");
+        assert_eq!(10_000, protocol.mix_dyn_stream("data", &mut reader).expect("should mix"));
+        assert_eq!(expected, protocol.derive_array::<16>("state"));
+
+        let mut reader: Box<dyn io::Read> = Box::new(Cursor::new(data.clone()));
+        let mut copy = Vec::new();
+        let mut protocol = Protocol::new("com.example.dyn");
+        assert_eq!(
+            10_000,
+            protocol.copy_dyn_stream("data", &mut reader, &mut copy).expect("should copy")
+        );
+        assert_eq!(data, copy);
+        assert_eq!(expected, protocol.derive_array::<16>("state"));
     }
-}
 
-/// All Lockstitch operation types.
-#[derive(Debug, Clone, Copy)]
-enum OpCode {
-    /// Initialize a protocol with a domain separation string.
-    Init = 0x01,
-    /// Mix a labeled input into the protocol transcript.
-    Mix = 0x02,
-    /// Derive a labeled output from the protocol transcript.
-    Derive = 0x03,
-    /// Encrypt or decrypt a labeled input using the protocol transcript as a key.
-    Crypt = 0x04,
-    /// Seal or open a labeled input using the protocol transcript as a key.
-    AuthCrypt = 0x05,
-}
+    #[test]
+    fn reencryption() {
+        let mut old = Protocol::new("com.example.rotation");
+        old.mix("key", b"an old key");
+        let mut new = Protocol::new("com.example.rotation");
+        new.mix("key", b"a new key");
 
-/// A [`std::io::Write`] implementation which combines all written data into a single `Mix`
-/// operation and passes all writes to an inner writer.
-#[cfg(feature = "std")]
-#[derive(Debug)]
-pub struct MixWriter<W> {
-    protocol: Protocol,
-    inner: W,
-    len: u64,
-}
+        let mut sealed = *b"a message\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        old.clone().seal("message", &mut sealed);
 
-#[cfg(feature = "std")]
-impl<W: std::io::Write> MixWriter<W> {
-    /// Finishes the `Mix` operation and returns the inner [`Protocol`] and writer.
-    #[inline]
-    pub fn into_inner(mut self) -> (Protocol, W) {
-        // Append the right-encoded length to the transcript.
-        self.protocol.transcript.update(right_encode(&mut [0u8; 9], self.len * 8));
-        (self.protocol, self.inner)
+        let mut rotated = sealed;
+        assert!(reencrypt(&mut old.clone(), &mut new.clone(), "message", &mut rotated));
+        assert_eq!(
+            new.clone().open("message", &mut rotated.clone()),
+            Some(b"a message".as_slice())
+        );
+        assert_eq!(old.clone().open("message", &mut rotated.clone()), None);
+
+        let mut tampered = sealed;
+        tampered[0] ^= 1;
+        let mut new_after = new.clone();
+        assert!(!reencrypt(&mut old.clone(), &mut new_after, "message", &mut tampered));
+        assert_eq!(tampered, [0u8; 9 + TAG_LEN]);
+        assert_eq!(new_after.derive_array::<16>("state"), new.derive_array::<16>("state"));
     }
-}
 
-#[cfg(feature = "std")]
-impl<W: std::io::Write> std::io::Write for MixWriter<W> {
-    #[inline]
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        // Track the written length.
-        self.len += buf.len() as u64;
-        // Append the written slice to the protocol transcript.
-        self.protocol.transcript.update(buf);
-        // Pass the slice to the inner writer and return the result.
-        self.inner.write(buf)
+    #[test]
+    fn empty_plaintexts() {
+        let mut protocol = Protocol::new("com.example.empty");
+        protocol.mix("key", b"a secret key");
+
+        let mut sender = protocol.clone();
+        let mut receiver = protocol.clone();
+
+        sender.encrypt("message", &mut []);
+        receiver.decrypt("message", &mut []);
+        expect!["b8fc24fab0e8c2fd45a64b1148960103"]
+            .assert_eq(&hex::encode(sender.clone().derive_array::<16>("state")));
+        assert_eq!(
+            sender.clone().derive_array::<16>("state"),
+            receiver.clone().derive_array::<16>("state")
+        );
+        assert_ne!(
+            sender.clone().derive_array::<16>("state"),
+            protocol.clone().derive_array::<16>("state")
+        );
+
+        let mut sealed = [0u8; TAG_LEN];
+        sender.seal("message", &mut sealed);
+        expect!["93441b852eb89719e388228425c021d5"].assert_eq(&hex::encode(sealed));
+        let mut forged = sealed;
+        forged[0] ^= 1;
+        assert_eq!(receiver.clone().open("message", &mut forged), None);
+        assert_eq!(receiver.open("message", &mut sealed), Some([].as_slice()));
+        assert_eq!(sender.derive_array::<16>("state"), receiver.derive_array::<16>("state"));
     }
 
-    #[inline]
-    fn flush(&mut self) -> std::io::Result<()> {
-        self.inner.flush()
+    #[test]
+    fn turns() {
+        let protocol = Protocol::new("com.example.turns");
+
+        let mut first = protocol.clone();
+        first.begin_turn(1);
+        first.mix("message", b"hello");
+        let mut second = protocol.clone();
+        second.begin_turn(2);
+        second.mix("message", b"hello");
+        assert_ne!(first.derive_array::<16>("state"), second.derive_array::<16>("state"));
+
+        // A message sealed in one turn doesn't open in another.
+        let mut sender = protocol.clone();
+        sender.begin_turn(1);
+        let mut sealed = *b"hello\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        sender.seal("message", &mut sealed);
+
+        let mut replayed = protocol.clone();
+        replayed.begin_turn(2);
+        assert_eq!(replayed.open("message", &mut sealed.clone()), None);
+
+        let mut receiver = protocol;
+        receiver.begin_turn(1);
+        assert_eq!(receiver.open("message", &mut sealed), Some(b"hello".as_slice()));
     }
-}
 
-/// Compares two slices for equality in constant time.
-#[inline]
-pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
-    let mut res = 1;
-    a.cmovne(b, 0, &mut res);
-    res != 0
-}
+    #[test]
+    fn mix_digest() {
+        use sha3::{Keccak256, Sha3_256};
+
+        let digest = Sha3_256::new_with_prefix(b"a large external object");
+        let output = digest.clone().finalize();
+
+        let mut expected = Protocol::new("com.example.digest");
+        expected.mix_str("digest-algorithm", "SHA3-256");
+        expected.mix("object", &output);
+
+        let mut protocol = Protocol::new("com.example.digest");
+        protocol.mix_digest("object", "SHA3-256", digest.clone());
+        assert_eq!(expected.derive_array::<16>("state"), protocol.derive_array::<16>("state"));
+
+        // The same output bytes under a different algorithm tag produce a different state.
+        let tagged = |algorithm: &str| {
+            let mut protocol = Protocol::new("com.example.digest");
+            protocol.mix_str("digest-algorithm", algorithm);
+            protocol.mix("object", &output);
+            protocol.derive_array::<16>("state")
+        };
+        assert_ne!(tagged("SHA3-256"), tagged("BLAKE3"));
+
+        let mut keccak = Protocol::new("com.example.digest");
+        keccak.mix_digest(
+            "object",
+            "Keccak-256",
+            Keccak256::new_with_prefix(b"a large external object"),
+        );
+        assert_ne!(tagged("SHA3-256"), keccak.derive_array::<16>("state"));
+    }
 
-/// Encodes a value using [NIST SP 800-185][]'s `right_encode`.
-///
-/// [NIST SP 800-185]: https://www.nist.gov/publications/sha-3-derived-functions-cshake-kmac-tuplehash-and-parallelhash
-#[inline]
-fn right_encode(buf: &mut [u8; 9], value: u64) -> &[u8] {
-    let len = buf.len();
-    buf[..len - 1].copy_from_slice(&value.to_be_bytes());
-    let n = (len - 1 - value.leading_zeros() as usize / 8).max(1);
-    buf[len - 1] = n as u8;
-    &buf[len - n - 1..]
-}
+    #[test]
+    fn mix_dh() {
+        let mut expected = Protocol::new("com.example.dh");
+        expected.mix("dh-initiator-key", b"initiator key");
+        expected.mix("dh-responder-key", b"responder key");
+        expected.mix("ee", b"a shared secret");
+
+        let mut protocol = Protocol::new("com.example.dh");
+        protocol.mix_dh("ee", b"initiator key", b"responder key", b"a shared secret");
+        assert_eq!(expected.derive_array::<16>("state"), protocol.derive_array::<16>("state"));
+
+        // The same shared secret from a different public key produces a different state.
+        let mut other = Protocol::new("com.example.dh");
+        other.mix_dh("ee", b"initiator key", b"another responder key", b"a shared secret");
+        assert_ne!(expected.derive_array::<16>("state"), other.derive_array::<16>("state"));
+    }
 
-#[cfg(all(test, feature = "std"))]
-mod tests {
-    use std::io::{self, Cursor};
+    #[test]
+    fn mix_dh_roles() {
+        // Each party knows its own public key and its peer's, and computes the same shared secret.
+        let party = |own_key: &[u8], peer_key: &[u8], is_initiator: bool| {
+            let (initiator_key, responder_key) =
+                if is_initiator { (own_key, peer_key) } else { (peer_key, own_key) };
+            let mut protocol = Protocol::new("com.example.dh");
+            protocol.mix_dh("ee", initiator_key, responder_key, b"a shared secret");
+            protocol.derive_array::<16>("state")
+        };
+
+        let initiator = party(b"initiator key", b"responder key", true);
+        let responder = party(b"responder key", b"initiator key", false);
+        assert_eq!(initiator, responder);
+
+        // Swapping the roles produces a different state.
+        assert_ne!(initiator, party(b"responder key", b"initiator key", true));
+    }
 
-    use expect_test::expect;
+    #[test]
+    fn checkpoint_restore() {
+        let mut expected = Protocol::new("com.example.checkpoint");
+        expected.mix("key", b"a secret key");
+        expected.encrypt("message", &mut [0u8; 10]);
+
+        let mut protocol = expected.clone();
+        let checkpoint = protocol.checkpoint();
+        protocol.mix("branch", b"a failed branch");
+        protocol.derive_array::<16>("output");
+        protocol.encrypt("message", &mut [0u8; 10]);
+        protocol.restore(checkpoint);
+
+        assert_eq!(expected.bytes_crypted(), protocol.bytes_crypted());
+        assert_eq!(expected.derive_array::<16>("state"), protocol.derive_array::<16>("state"));
+    }
 
-    use super::*;
+    #[test]
+    fn seal_to_writer() {
+        let mut protocol = Protocol::new("com.example.seal-to-writer");
+        protocol.mix("key", b"a secret key");
+
+        for len in [0, 1, 31, 32, 4095, 4096, 4097, 10_000] {
+            let plaintext = (0..len).map(|i| i as u8).collect::<Vec<u8>>();
+
+            let mut expected = protocol.clone();
+            let mut sealed = plaintext.clone();
+            sealed.extend_from_slice(&[0u8; TAG_LEN]);
+            expected.seal("message", &mut sealed);
+
+            let mut streamed = protocol.clone();
+            let mut output = Vec::new();
+            streamed.seal_to_writer("message", &plaintext, &mut output).expect("should write");
+            assert_eq!(sealed, output, "len = {len}");
+            assert_eq!(
+                expected.derive_array::<16>("state"),
+                streamed.derive_array::<16>("state"),
+                "len = {len}"
+            );
+        }
+
+        // A failing writer returns an error but advances the state exactly as seal does.
+        let mut expected = protocol.clone();
+        expected.seal("message", &mut [0u8; 10_000 + TAG_LEN]);
+        let mut streamed = protocol.clone();
+        let mut output = [0u8; 100];
+        assert!(streamed.seal_to_writer("message", &[0u8; 10_000], output.as_mut_slice()).is_err());
+        assert_eq!(expected.derive_array::<16>("state"), streamed.derive_array::<16>("state"));
+    }
 
     #[test]
-    fn known_answers() {
-        let mut protocol = Protocol::new("com.example.kat");
-        protocol.mix("first", b"one");
-        protocol.mix("second", b"two");
+    fn derive_select() {
+        let mut protocol = Protocol::new("com.example.select");
+        protocol.mix("key", b"a secret key");
+
+        let mut expected = protocol.clone();
+        let a = expected.derive_array::<16>("candidate");
+        let b = expected.derive_array::<16>("candidate");
+        assert_ne!(a, b);
+
+        // Both candidates are derived regardless of the condition.
+        for (cond, selected) in [(false, a), (true, b)] {
+            let mut protocol = protocol.clone();
+            assert_eq!(selected, protocol.derive_select::<16>("candidate", cond));
+            assert_eq!(
+                expected.clone().derive_array::<16>("state"),
+                protocol.derive_array::<16>("state")
+            );
+        }
+    }
 
-        expect!["9d741fc2d9c5cba0"].assert_eq(&hex::encode(protocol.derive_array::<8>("third")));
+    #[test]
+    fn ct_select() {
+        assert_eq!([1, 2, 3], super::ct_select(&[1, 2, 3], &[4, 5, 6], false));
+        assert_eq!([4, 5, 6], super::ct_select(&[1, 2, 3], &[4, 5, 6], true));
+    }
 
-        let mut plaintext = b"this is an example".to_vec();
-        protocol.encrypt("fourth", &mut plaintext);
-        expect!["ec324ce127e09da0b60bf87199acd016969a"].assert_eq(&hex::encode(plaintext));
+    #[test]
+    fn seal_stream() {
+        let mut protocol = Protocol::new("com.example.seal-stream");
+        protocol.mix("key", b"a secret key");
+
+        for len in [0, 1, 4095, 4096, 10_000] {
+            let plaintext = (0..len).map(|i| i as u8).collect::<Vec<u8>>();
+
+            let mut expected = protocol.clone();
+            let mut sealed = plaintext.clone();
+            sealed.extend_from_slice(&[0u8; TAG_LEN]);
+            expected.seal("message", &mut sealed);
+
+            let mut streamed = protocol.clone();
+            let mut output = Vec::new();
+            streamed
+                .seal_stream("message", len as u64, plaintext.as_slice(), &mut output)
+                .expect("should seal");
+            assert_eq!(sealed, output, "len = {len}");
+            assert_eq!(
+                expected.derive_array::<16>("state"),
+                streamed.derive_array::<16>("state"),
+                "len = {len}"
+            );
+        }
 
-        let plaintext = b"this is an example";
-        let mut sealed = vec![0u8; plaintext.len() + TAG_LEN];
-        sealed[..plaintext.len()].copy_from_slice(plaintext);
-        protocol.seal("fifth", &mut sealed);
+        let err = protocol
+            .clone()
+            .seal_stream("message", 100, [0u8; 99].as_slice(), io::sink())
+            .expect_err("should fail");
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
 
-        expect!["9aec57dd29ad1dfd45ca56098e26bdbb928d39e23c9bf64a712a9d04adfab8803707"]
-            .assert_eq(&hex::encode(sealed));
+    #[test]
+    fn seal_stream_large_len() {
+        // A length over 4 GiB is mixed in full, not truncated to 32 bits.
+        let len = (5u64 << 30) + 1;
+
+        let mut expected = Protocol::new("com.example.seal-stream");
+        expected.op_header(OpCode::AuthCrypt, "message");
+        expected.mix_int("len", len * 8);
+        let mut expected = expected.derive_aegis_128l("key");
+
+        let mut protocol = Protocol::new("com.example.seal-stream");
+        let mut aegis = protocol.begin_seal("message", len, None);
+        assert_eq!(len, protocol.bytes_crypted());
+
+        let mut truncated = Protocol::new("com.example.seal-stream");
+        let mut truncated = truncated.begin_seal("message", u64::from(len as u32), None);
+
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        let mut c = [0u8; 32];
+        expected.prf(&mut a);
+        aegis.prf(&mut b);
+        truncated.prf(&mut c);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
 
-        expect!["21d58fc6560a5c49"].assert_eq(&hex::encode(protocol.derive_array::<8>("sixth")));
+    #[test]
+    fn object_key() {
+        let mut master = Protocol::new("com.example.object-store");
+        master.mix("key", b"a master key");
+
+        let mut expected = master.clone();
+        expected.mix("object-key", b"object-1");
+        assert_eq!(expected.derive_array::<32>("key"), master.object_key(b"object-1"));
+
+        assert_eq!(master.object_key(b"object-1"), master.object_key(b"object-1"));
+        assert_ne!(master.object_key(b"object-1"), master.object_key(b"object-2"));
+        assert_ne!(master.object_key(b""), master.object_key(b"object-1"));
     }
 
     #[test]
-    fn readers() {
-        let mut slices = Protocol::new("com.example.streams");
-        slices.mix("first", b"one");
-        slices.mix("second", b"two");
+    fn mix_sorted_pairs() {
+        let mixed = |pairs: &mut [(&[u8], &[u8])]| {
+            let mut protocol = Protocol::new("com.example.pairs");
+            protocol.mix_sorted_pairs("headers", pairs);
+            protocol.derive_array::<16>("state")
+        };
+
+        let mut expected = Protocol::new("com.example.pairs");
+        expected.mix_int("headers", 2);
+        expected.mix("key", b"accept");
+        expected.mix("value", b"text/plain");
+        expected.mix("key", b"host");
+        expected.mix("value", b"example.com");
+        let expected = expected.derive_array::<16>("state");
+
+        // The order of the pairs doesn't matter.
+        assert_eq!(expected, mixed(&mut [(b"host", b"example.com"), (b"accept", b"text/plain")]));
+        assert_eq!(expected, mixed(&mut [(b"accept", b"text/plain"), (b"host", b"example.com")]));
+
+        // Moving bytes between keys and values changes the transcript.
+        assert_ne!(expected, mixed(&mut [(b"hos", b"texample.com"), (b"accept", b"text/plain")]));
+        assert_ne!(mixed(&mut []), mixed(&mut [(b"", b"")]));
+    }
 
-        let streams = Protocol::new("com.example.streams");
-        let mut streams_write = streams.mix_writer("first", io::sink());
-        io::copy(&mut Cursor::new(b"one"), &mut streams_write)
-            .expect("cursor reads and sink writes should be infallible");
-        let (streams, _) = streams_write.into_inner();
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "duplicate key")]
+    fn mix_sorted_pairs_duplicate_keys() {
+        let mut protocol = Protocol::new("com.example.pairs");
+        protocol.mix_sorted_pairs("headers", &mut [(b"host", b"a"), (b"host", b"b")]);
+    }
 
-        let mut output = Vec::new();
-        let mut streams_write = streams.mix_writer("second", &mut output);
-        io::copy(&mut Cursor::new(b"two"), &mut streams_write)
-            .expect("cursor reads and sink writes should be infallible");
-        let (mut streams, output) = streams_write.into_inner();
+    #[test]
+    fn seal_padded() {
+        let mut protocol = Protocol::new("com.example.padded");
+        protocol.mix("key", b"a secret key");
+
+        for len in [0, 1, 23, 24, 25, 31, 32, 33, 100] {
+            let plaintext = vec![0xAB; len];
+            let sealed = protocol.clone().seal_padded("message", &plaintext, 32);
+            assert_eq!((8 + len).next_multiple_of(32) + TAG_LEN, sealed.len(), "len = {len}");
+            assert_eq!(
+                Some(plaintext),
+                protocol.clone().open_padded("message", &sealed),
+                "len = {len}"
+            );
+        }
 
-        assert_eq!(slices.derive_array::<16>("third"), streams.derive_array::<16>("third"));
-        assert_eq!(b"two".as_slice(), output);
+        // Plaintexts in the same bucket have the same sealed length.
+        assert_eq!(
+            protocol.clone().seal_padded("message", b"", 64).len(),
+            protocol.clone().seal_padded("message", &[0u8; 56], 64).len()
+        );
+
+        // The length prefix and padding are authenticated.
+        let sealed = protocol.clone().seal_padded("message", b"hello", 32);
+        for i in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert_eq!(None, protocol.clone().open_padded("message", &tampered), "i = {i}");
+        }
     }
 
     #[test]
-    fn edge_case() {
-        let mut sender = Protocol::new("");
-        let mut message = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
-        sender.encrypt("message", &mut message);
-        let tag_s = sender.derive_array::<TAG_LEN>("tag");
+    fn open_padded_malformed() {
+        let mut protocol = Protocol::new("com.example.padded");
+        protocol.mix("key", b"a secret key");
+
+        // A length prefix longer than the padded plaintext.
+        let mut sealed = [0u8; 32 + TAG_LEN];
+        sealed[..8].copy_from_slice(&25u64.to_be_bytes());
+        protocol.clone().seal("message", &mut sealed);
+        assert_eq!(None, protocol.clone().open_padded("message", &sealed));
+
+        // Non-zero padding.
+        let mut sealed = [1u8; 32 + TAG_LEN];
+        sealed[..8].copy_from_slice(&5u64.to_be_bytes());
+        protocol.clone().seal("message", &mut sealed);
+        assert_eq!(None, protocol.clone().open_padded("message", &sealed));
+
+        // A padded plaintext too short for a length prefix.
+        let mut sealed = [0u8; 7 + TAG_LEN];
+        protocol.clone().seal("message", &mut sealed);
+        assert_eq!(None, protocol.clone().open_padded("message", &sealed));
+    }
 
-        let mut receiver = Protocol::new("");
-        receiver.decrypt("message", &mut message);
-        let tag_r = receiver.derive_array::<TAG_LEN>("tag");
+    #[test]
+    fn control_frames() {
+        let mut sender = Protocol::new("com.example.transport");
+        sender.mix("key", b"a secret key");
+        let mut receiver = sender.clone();
+
+        let mut expected = sender.clone();
+        expected.mix("ping", b"1");
+        assert_eq!(expected.send_mac("ping"), sender.clone().send_control("ping", b"1"));
+
+        // Control and data frames can be interleaved and stay in sync.
+        for i in 0..3u8 {
+            let mac = sender.send_control("ping", &[i]);
+            assert!(receiver.recv_control("ping", &[i], &mac));
+
+            let mut data = [i; 10 + TAG_LEN];
+            sender.seal("data", &mut data);
+            assert_eq!(Some([i; 10].as_slice()), receiver.open("data", &mut data));
+        }
 
-        assert_eq!(message, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
-        assert_eq!(tag_s, tag_r);
+        // A tampered control frame is rejected.
+        let mac = sender.clone().send_control("ping", b"ping");
+        assert!(!receiver.clone().recv_control("ping", b"pong", &mac));
+        assert!(!receiver.clone().recv_control("ping", b"ping", &mac[..TAG_LEN - 1]));
+
+        // A control frame can't be substituted for a data frame, or vice versa.
+        let mac = sender.clone().send_control("data", b"0123456789");
+        let mut frame = [b"0123456789".as_slice(), &mac].concat();
+        assert_eq!(None, receiver.clone().open("data", &mut frame));
+
+        let mut data = [0u8; 10 + TAG_LEN];
+        sender.clone().seal("data", &mut data);
+        let (ciphertext, tag) = data.split_at(10);
+        assert!(!receiver.clone().recv_control("data", ciphertext, tag));
+    }
+
+    #[test]
+    #[cfg(feature = "bytemuck")]
+    fn derive_pod() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(C)]
+        struct Keys {
+            enc: [u8; 16],
+            mac: [u8; 32],
+            counter: u64,
+        }
+
+        // SAFETY: Keys is repr(C), contains only plain-old-data fields, and has no padding.
+        unsafe impl bytemuck::Zeroable for Keys {}
+        unsafe impl bytemuck::Pod for Keys {}
+
+        let mut protocol = Protocol::new("com.example.pod");
+        protocol.mix("key", b"a secret key");
+
+        let bytes = protocol.clone().derive_array::<56>("keys");
+        let keys = protocol.derive_pod::<Keys>("keys");
+        assert_eq!(bytes.as_slice(), bytemuck::bytes_of(&keys));
+        assert_eq!(bytes[..16], keys.enc);
+        assert_eq!(bytes[16..48], keys.mac);
+        assert_eq!(
+            u64::from_ne_bytes(bytes[48..].try_into().expect("should be 8 bytes")),
+            keys.counter
+        );
+    }
+
+    #[test]
+    fn rotate_domain() {
+        let mut handshake = Protocol::new("com.example.handshake");
+        handshake.mix("key", b"a shared secret");
+
+        let mut expected = handshake.clone();
+        expected.mix_str("domain", "com.example.transport");
+        expected.ratchet("rotate-domain");
+
+        let mut transport = handshake.clone();
+        transport.rotate_domain("com.example.transport");
+        assert_eq!(
+            expected.derive_array::<16>("state"),
+            transport.clone().derive_array::<16>("state")
+        );
+
+        // Phases with different domains diverge.
+        let mut resumption = handshake.clone();
+        resumption.rotate_domain("com.example.resumption");
+        assert_ne!(
+            transport.clone().derive_array::<16>("state"),
+            resumption.derive_array::<16>("state")
+        );
+
+        // The new phase is bound to the handshake.
+        let mut other = Protocol::new("com.example.handshake");
+        other.mix("key", b"another shared secret");
+        other.rotate_domain("com.example.transport");
+        assert_ne!(
+            transport.clone().derive_array::<16>("state"),
+            other.derive_array::<16>("state")
+        );
+
+        // The new phase is distinct from a fresh protocol with the same domain.
+        assert_ne!(
+            transport.derive_array::<16>("state"),
+            Protocol::new("com.example.transport").derive_array::<16>("state")
+        );
+    }
+
+    #[test]
+    fn mixers() {
+        let data = (0..=255u8).cycle().take(1_000).collect::<Vec<u8>>();
+        let mut expected = Protocol::new("com.example.mixer");
+        expected.mix("data", &data);
+        expected.mix("empty", &[]);
+        let expected = expected.derive_array::<16>("state");
+
+        let mut protocol = Protocol::new("com.example.mixer");
+        protocol.mixer("data").extend(data.iter().copied());
+        protocol.mixer("empty").finish();
+        assert_eq!(expected, protocol.derive_array::<16>("state"));
+
+        let mut protocol = Protocol::new("com.example.mixer");
+        let mut mixer = protocol.mixer("data");
+        mixer.extend(&data[..100]);
+        mixer.write_all(&data[100..500]).expect("mixer writes should be infallible");
+        mixer.extend(data[500..].iter().copied());
+        mixer.finish();
+        protocol.mixer("empty");
+        assert_eq!(expected, protocol.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn tag_first() {
+        let mut protocol = Protocol::new("com.example.tag-first");
+        protocol.mix("key", b"a secret key");
+
+        let mut tag_last = *b"a message\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        protocol.clone().seal("message", &mut tag_last);
+
+        let mut tag_first = *b"\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0a message";
+        protocol.clone().seal_tag_first("message", &mut tag_first);
+        assert_eq!(tag_first[..TAG_LEN], tag_last[9..]);
+        assert_eq!(tag_first[TAG_LEN..], tag_last[..9]);
+
+        let mut reordered = tag_last;
+        reordered.rotate_right(TAG_LEN);
+        assert_eq!(reordered, tag_first);
+        assert_eq!(
+            protocol.clone().open_tag_first("message", &mut reordered),
+            Some(b"a message".as_slice())
+        );
+
+        let mut forged = tag_first;
+        forged[0] ^= 1;
+        assert_eq!(protocol.clone().open_tag_first("message", &mut forged), None);
+        assert_eq!(forged[TAG_LEN..], [0u8; 9]);
+
+        let mut short = [0u8; TAG_LEN - 1];
+        assert_eq!(protocol.open_tag_first("message", &mut short), None);
+    }
+
+    #[test]
+    fn open_to_vec() {
+        let mut sender = Protocol::new("com.example.open");
+        sender.mix("key", b"a secret key");
+        let mut receiver = sender.clone();
+
+        let mut sealed = b"a message".to_vec();
+        sealed.extend_from_slice(&[0u8; TAG_LEN]);
+        sender.seal("message", &mut sealed);
+
+        assert_eq!(receiver.clone().open_to_vec("message", &sealed[..TAG_LEN - 1]), None);
+
+        let mut forged = sealed.clone();
+        forged[0] ^= 1;
+        assert_eq!(receiver.clone().open_to_vec("message", &forged), None);
+
+        assert_eq!(receiver.open_to_vec("message", &sealed), Some(b"a message".to_vec()));
+        assert_eq!(receiver.derive_array::<16>("state"), sender.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn ad_and_message_boundaries_are_bound() {
+        let protocol = Protocol::new("com.example.boundaries");
+        let message = b"associated datamessage";
+
+        // Seal the same bytes with every possible split between associated data and plaintext.
+        let tags = (0..=message.len())
+            .map(|split| {
+                let (ad, pt) = message.split_at(split);
+                let mut sealed = pt.to_vec();
+                sealed.extend_from_slice(&[0u8; TAG_LEN]);
+                let mut protocol = protocol.clone();
+                protocol.mix("ad", ad);
+                protocol.seal("message", &mut sealed);
+                sealed[pt.len()..].to_vec()
+            })
+            .collect::<Vec<_>>();
+
+        for (i, a) in tags.iter().enumerate() {
+            for b in &tags[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+
+        // Moving a byte from the associated data to the ciphertext fails to open.
+        let mut sender = protocol.clone();
+        sender.mix("ad", b"associated data");
+        let mut sealed = *b"message\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        sender.seal("message", &mut sealed);
+
+        let mut receiver = protocol;
+        receiver.mix("ad", b"associated dat");
+        let mut moved = b"a".to_vec();
+        moved.extend_from_slice(&sealed);
+        assert_eq!(receiver.open("message", &mut moved), None);
+    }
+
+    #[test]
+    fn seal_tags_are_separated_from_derive_output() {
+        let mut protocol = Protocol::new("com.example.separation");
+        protocol.mix("key", b"a secret key");
+
+        for len in [0, 1, 15, 16, 17, 31, 32, 33, 64] {
+            let mut sealed = vec![0u8; len + TAG_LEN];
+            protocol.clone().seal("message", &mut sealed);
+            let tag = &sealed[len..];
+
+            assert_ne!(tag, protocol.clone().derive_array::<TAG_LEN>("message"), "len = {len}");
+            assert_ne!(tag, protocol.clone().send_mac("message"), "len = {len}");
+        }
+    }
+
+    #[test]
+    fn commitments() {
+        let value = vec![7u8; 100_000];
+
+        let mut committer = Protocol::new("com.example.commit");
+        committer.mix("nonce", b"a random nonce");
+        let mut verifier = committer.clone();
+
+        let mut expected = committer.clone();
+        expected.mix("value", &value);
+        let expected = expected.derive_array::<32>("value");
+
+        let commitment = committer.commit_stream("value", &value[..]).expect("should commit");
+        assert_eq!(commitment, expected);
+
+        let mut forged = value.clone();
+        forged[99_999] ^= 1;
+        assert!(!verifier
+            .clone()
+            .verify_commitment("value", &forged[..], &commitment)
+            .expect("should verify"));
+
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("failed"))
+            }
+        }
+        assert!(verifier.verify_commitment("value", Failing, &commitment).is_err());
+
+        assert!(verifier
+            .verify_commitment("value", &value[..], &commitment)
+            .expect("should verify"));
+        assert_eq!(verifier.derive_array::<16>("state"), committer.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn macs() {
+        let mut sender = Protocol::new("com.example.mac");
+        sender.mix("message", b"a message");
+        let mut receiver = sender.clone();
+
+        let mac = sender.send_mac("mac");
+        assert_ne!(mac, receiver.clone().derive_array::<TAG_LEN>("mac"));
+        expect!["3d8a87f1e16d40b8863f7c736f384971"].assert_eq(&hex::encode(mac));
+
+        let mut forged = mac;
+        forged[0] ^= 1;
+        assert!(!receiver.clone().recv_mac("mac", &forged));
+        assert!(!receiver.clone().recv_mac("mac", &mac[..8]));
+
+        let mut truncated = receiver.clone();
+        assert!(!truncated.recv_mac("mac", &[]));
+        assert_eq!(
+            truncated.derive_array::<16>("state"),
+            sender.clone().derive_array::<16>("state")
+        );
+
+        assert!(receiver.recv_mac("mac", &mac));
+        assert_eq!(receiver.derive_array::<16>("state"), sender.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn sync_beacons() {
+        let mut a = Protocol::new("com.example.beacon");
+        a.mix("key", b"a secret key");
+        let mut b = a.clone();
+
+        assert_eq!(a.sync_beacon(), b.sync_beacon());
+        assert_eq!(a.sync_beacon(), a.clone().derive_array::<16>("sync-beacon"));
+
+        a.mix("message", b"one");
+        b.mix("message", b"two");
+        assert_ne!(a.sync_beacon(), b.sync_beacon());
+
+        // Taking a beacon doesn't modify the protocol's state.
+        let before = a.clone().derive_array::<16>("state");
+        let _ = a.sync_beacon();
+        assert_eq!(before, a.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn verify_derived() {
+        let mut protocol = Protocol::new("com.example.resumption");
+        protocol.mix("key", b"a secret key");
+
+        for len in [1, 8, 16, 32, 167, 168, 169, 500] {
+            let mut token = vec![0u8; len];
+            protocol.clone().derive("token", &mut token);
+
+            assert!(protocol.clone().verify_derived("token", &token), "len = {len}");
+            assert!(!protocol.clone().verify_derived("other", &token), "len = {len}");
+            assert!(!protocol.clone().verify_derived("token", &token[1..]), "len = {len}");
+
+            let mut forged = token.clone();
+            forged[len - 1] ^= 1;
+            assert!(!protocol.clone().verify_derived("token", &forged), "len = {len}");
+        }
+
+        let mut empty = protocol.clone();
+        assert!(!empty.verify_derived("token", &[]));
+        assert_eq!(empty.derive_array::<16>("state"), {
+            protocol.derive("token", &mut []);
+            protocol.derive_array::<16>("state")
+        });
+    }
+
+    #[test]
+    fn derive_aes256gcm() {
+        let mut protocol = Protocol::new("com.example.gcm");
+        protocol.mix("key", b"a secret key");
+        let mut expected = protocol.clone();
+
+        let (key, nonce) = protocol.derive_aes256gcm("gcm");
+        let kn = expected.derive_array::<44>("gcm");
+        assert_eq!(kn[..32], key);
+        assert_eq!(kn[32..], nonce);
+
+        expect!["efe048b8698e83d4c6e2096a458450589f3412bce419520ccd37f763c76a4b5d"]
+            .assert_eq(&hex::encode(key));
+        expect!["3c63a1d198ec9514d562b565"].assert_eq(&hex::encode(nonce));
+    }
+
+    #[test]
+    fn derive_aegis() {
+        let mut sender = Protocol::new("com.example.bulk");
+        sender.mix("key", b"a secret key");
+        let mut receiver = sender.clone();
+
+        let mut payload = vec![0xA5; 1000];
+        let mut aegis = sender.derive_aegis("payload");
+        aegis.encrypt(&mut payload);
+        let tag = sender.seal_aegis("payload", aegis);
+        assert_ne!(vec![0xA5; 1000], payload);
+
+        let mut forged = receiver.clone();
+        let mut forged_payload = payload.clone();
+        forged_payload[500] ^= 1;
+        let mut aegis = forged.derive_aegis("payload");
+        aegis.decrypt(&mut forged_payload);
+        assert!(!forged.open_aegis("payload", aegis, &tag));
+
+        let mut aegis = receiver.derive_aegis("payload");
+        aegis.decrypt(&mut payload);
+        assert!(receiver.open_aegis("payload", aegis, &tag));
+        assert_eq!(vec![0xA5; 1000], payload);
+
+        // Both sides fold the same commitment back into their transcripts.
+        assert_eq!(sender.derive_array::<16>("state"), receiver.derive_array::<16>("state"));
+        assert_ne!(forged.derive_array::<16>("state"), sender.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn derive_aegis_is_not_derive() {
+        let mut protocol = Protocol::new("com.example.bulk");
+        protocol.mix("key", b"a secret key");
+
+        // Derive output with the same label at the same state doesn't reveal the cipher's key.
+        let kn = protocol.clone().derive_array::<32>("payload");
+        let (k, n) = kn.split_at(aegis_128l::KEY_LEN);
+        let mut derived = Aegis128L::new(
+            k.try_into().expect("should be KEY_LEN bytes"),
+            n.try_into().expect("should be NONCE_LEN bytes"),
+        );
+        let mut aegis = protocol.derive_aegis("payload");
+
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        derived.prf(&mut a);
+        aegis.prf(&mut b);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn short_hash() {
+        let key = [7u8; aegis_128l::KEY_LEN];
+        expect!["502540710975244595"].assert_eq(&super::short_hash(&key, b"").to_string());
+        expect!["8346386237702231073"]
+            .assert_eq(&super::short_hash(&key, b"a key in a hash table").to_string());
+
+        assert_ne!(super::short_hash(&key, b"one"), super::short_hash(&key, b"two"));
+        assert_ne!(super::short_hash(&key, b"one"), super::short_hash(&[8u8; 16], b"one"));
     }
 
     #[test]
@@ -448,6 +3732,27 @@ mod tests {
         });
     }
 
+    #[test]
+    fn length_encoding_is_platform_independent() {
+        // A 512 MiB input has a length of 2^32 bits, which would overflow a 32-bit encoding.
+        let len: usize = 512 * 1024 * 1024;
+        assert_eq!(right_encode(&mut [0u8; 9], len as u64 * 8), [1, 0, 0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn right_encode_widths() {
+        assert_eq!(right_encode(&mut [0u8; 9], 0), [0, 1]);
+        for bits in 1..=64u32 {
+            let value = u64::MAX >> (64 - bits);
+            let n = bits.div_ceil(8) as usize;
+            let mut buf = [0u8; 9];
+            let encoded = right_encode(&mut buf, value);
+            assert_eq!(encoded.len(), n + 1, "bits = {bits}");
+            assert_eq!(encoded[n], n as u8, "bits = {bits}");
+            assert_eq!(&encoded[..n], &value.to_be_bytes()[8 - n..], "bits = {bits}");
+        }
+    }
+
     #[test]
     fn right_encode_test_vectors() {
         let mut buf = [0; 9];
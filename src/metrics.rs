@@ -0,0 +1,98 @@
+//! Process-wide counters of protocol operations, for observability.
+//!
+//! Every operation appended to any protocol's transcript increments a counter for its type. This
+//! includes the operations which other operations perform internally: for example, each
+//! [`Protocol::seal`](crate::Protocol::seal) counts as one `AuthCrypt` operation, one `Derive`
+//! operation, and several `Mix` operations.
+//!
+//! The counters are relaxed atomics, so they may be incremented from any thread. A
+//! [`snapshot`] is not an atomic view of all counters at once, and concurrent operations may be
+//! reflected in some counters but not others. The counters are for capacity planning and similar
+//! telemetry, and have no bearing on the security of any protocol.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::OpCode;
+
+static INIT: AtomicU64 = AtomicU64::new(0);
+static MIX: AtomicU64 = AtomicU64::new(0);
+static DERIVE: AtomicU64 = AtomicU64::new(0);
+static CRYPT: AtomicU64 = AtomicU64::new(0);
+static AUTH_CRYPT: AtomicU64 = AtomicU64::new(0);
+static MAC: AtomicU64 = AtomicU64::new(0);
+static DERIVE_CIPHER: AtomicU64 = AtomicU64::new(0);
+
+/// The number of operations of each type performed by all protocols in the process.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Snapshot {
+    /// The number of `Init` operations.
+    pub init: u64,
+    /// The number of `Mix` operations.
+    pub mix: u64,
+    /// The number of `Derive` operations.
+    pub derive: u64,
+    /// The number of `Encrypt`/`Decrypt` operations.
+    pub crypt: u64,
+    /// The number of `Seal`/`Open` operations.
+    pub auth_crypt: u64,
+    /// The number of `SendMAC`/`RecvMAC` operations.
+    pub mac: u64,
+    /// The number of `DeriveCipher` operations.
+    pub derive_cipher: u64,
+}
+
+/// Returns the number of operations of each type performed so far by all protocols in the
+/// process.
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        init: INIT.load(Ordering::Relaxed),
+        mix: MIX.load(Ordering::Relaxed),
+        derive: DERIVE.load(Ordering::Relaxed),
+        crypt: CRYPT.load(Ordering::Relaxed),
+        auth_crypt: AUTH_CRYPT.load(Ordering::Relaxed),
+        mac: MAC.load(Ordering::Relaxed),
+        derive_cipher: DERIVE_CIPHER.load(Ordering::Relaxed),
+    }
+}
+
+/// Increments the counter for the given operation.
+#[inline]
+pub(crate) fn record(op_code: OpCode) {
+    let counter = match op_code {
+        OpCode::Init => &INIT,
+        OpCode::Mix => &MIX,
+        OpCode::Derive => &DERIVE,
+        OpCode::Crypt => &CRYPT,
+        OpCode::AuthCrypt => &AUTH_CRYPT,
+        OpCode::Mac => &MAC,
+        OpCode::DeriveCipher => &DERIVE_CIPHER,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{Protocol, TAG_LEN};
+
+    #[test]
+    fn counts_operations() {
+        // Other tests run concurrently, so only check that the counters advance.
+        let before = snapshot();
+
+        let mut protocol = Protocol::new("com.example.metrics");
+        protocol.mix("key", b"a secret key");
+        protocol.encrypt("message", &mut [0u8; 8]);
+        protocol.seal("message", &mut [0u8; 8 + TAG_LEN]);
+        let _ = protocol.send_mac("mac");
+
+        let after = snapshot();
+        assert!(after.init > before.init);
+        assert!(after.mix > before.mix);
+        assert!(after.derive >= before.derive + 2);
+        assert!(after.crypt > before.crypt);
+        assert!(after.auth_crypt > before.auth_crypt);
+        assert!(after.mac > before.mac);
+    }
+}
@@ -0,0 +1,148 @@
+//! A [Noise Protocol Framework][Noise]-style vocabulary for [`Protocol`].
+//!
+//! [`SymmetricState`] exposes the operations of Noise's `SymmetricState` object on top of a
+//! [`Protocol`], making ports of Noise-shaped handshakes a mechanical translation:
+//!
+//! | Noise                 | Lockstitch                                                |
+//! |-----------------------|-----------------------------------------------------------|
+//! | `InitializeSymmetric` | [`Protocol::new`] with the protocol name                  |
+//! | `MixKey(ikm)`         | [`Protocol::mix`] with the label `key`                    |
+//! | `MixHash(data)`       | [`Protocol::mix`] with the label `hash`                   |
+//! | `MixKeyAndHash(ikm)`  | [`Protocol::mix`] with the label `key-and-hash`           |
+//! | `GetHandshakeHash()`  | [`Protocol::derive`] with the label `handshake-hash`¹     |
+//! | `EncryptAndHash(p)`   | [`Protocol::seal`] with the label `message`               |
+//! | `DecryptAndHash(c)`   | [`Protocol::open`] with the label `message`               |
+//! | `Split()`             | [`Protocol::mix`] with the label `split` on two copies    |
+//!
+//! ¹ Performed on a clone of the protocol, leaving the state unmodified.
+//!
+//! Unlike Noise, which passes plaintext through `EncryptAndHash` unmodified until a key has been
+//! established, a Lockstitch protocol always seals messages. Until a secret has been mixed in with
+//! [`SymmetricState::mix_key`], those messages are authenticated with respect to the transcript but
+//! provide no confidentiality.
+//!
+//! [Noise]: https://noiseprotocol.org/noise.html
+
+use crate::Protocol;
+
+/// A Noise `SymmetricState` object backed by a [`Protocol`].
+#[derive(Debug, Clone)]
+pub struct SymmetricState {
+    protocol: Protocol,
+}
+
+impl SymmetricState {
+    /// Creates a new symmetric state with the given Noise protocol name as the domain.
+    #[inline]
+    pub fn new(protocol_name: &str) -> SymmetricState {
+        SymmetricState { protocol: Protocol::new(protocol_name) }
+    }
+
+    /// Mixes the given input key material (e.g. a Diffie-Hellman shared secret) into the state.
+    #[inline]
+    pub fn mix_key(&mut self, input_key_material: &[u8]) {
+        self.protocol.mix("key", input_key_material);
+    }
+
+    /// Mixes the given public data (e.g. an ephemeral public key) into the state.
+    #[inline]
+    pub fn mix_hash(&mut self, data: &[u8]) {
+        self.protocol.mix("hash", data);
+    }
+
+    /// Mixes the given input key material (e.g. a pre-shared key) into the state.
+    #[inline]
+    pub fn mix_key_and_hash(&mut self, input_key_material: &[u8]) {
+        self.protocol.mix("key-and-hash", input_key_material);
+    }
+
+    /// Returns a hash of the handshake transcript, suitable for channel binding.
+    ///
+    /// This does not modify the state.
+    #[inline]
+    pub fn handshake_hash(&self) -> [u8; 32] {
+        self.protocol.clone().derive_array("handshake-hash")
+    }
+
+    /// Seals the given mutable slice in place.
+    ///
+    /// The last [`TAG_LEN`](crate::TAG_LEN) bytes of the slice will be overwritten with the
+    /// authentication tag.
+    #[inline]
+    pub fn encrypt_and_hash(&mut self, in_out: &mut [u8]) {
+        self.protocol.seal("message", in_out);
+    }
+
+    /// Opens the given mutable slice in place. Returns the plaintext slice of `in_out` if the input
+    /// was authenticated.
+    #[inline]
+    #[must_use]
+    pub fn decrypt_and_hash<'ct>(&mut self, in_out: &'ct mut [u8]) -> Option<&'ct [u8]> {
+        self.protocol.open("message", in_out)
+    }
+
+    /// Splits the state into a pair of protocols for transport messages, the first for messages
+    /// sent by the initiator and the second for messages sent by the responder.
+    #[inline]
+    pub fn split(self) -> (Protocol, Protocol) {
+        let mut initiator = self.protocol;
+        let mut responder = initiator.clone();
+        initiator.mix("split", b"initiator");
+        responder.mix("split", b"responder");
+        (initiator, responder)
+    }
+
+    /// Returns the underlying [`Protocol`].
+    #[inline]
+    pub const fn into_protocol(self) -> Protocol {
+        self.protocol
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::TAG_LEN;
+
+    use super::*;
+
+    #[test]
+    fn handshake() {
+        let mut initiator = SymmetricState::new("Noise_NN_Lockstitch");
+        let mut responder = SymmetricState::new("Noise_NN_Lockstitch");
+
+        // -> e
+        initiator.mix_hash(b"initiator ephemeral");
+        responder.mix_hash(b"initiator ephemeral");
+
+        // <- e, ee
+        responder.mix_hash(b"responder ephemeral");
+        responder.mix_key(b"ee shared secret");
+        initiator.mix_hash(b"responder ephemeral");
+        initiator.mix_key(b"ee shared secret");
+
+        let mut message = b"a handshake payload".to_vec();
+        message.extend_from_slice(&[0u8; TAG_LEN]);
+        responder.encrypt_and_hash(&mut message);
+        assert_eq!(
+            Some(b"a handshake payload".as_slice()),
+            initiator.decrypt_and_hash(&mut message)
+        );
+
+        assert_eq!(initiator.handshake_hash(), responder.handshake_hash());
+
+        let (mut i_tx, mut i_rx) = initiator.split();
+        let (mut r_rx, mut r_tx) = responder.split();
+
+        let mut message = b"initiator to responder".to_vec();
+        message.extend_from_slice(&[0u8; TAG_LEN]);
+        i_tx.seal("message", &mut message);
+        assert_eq!(Some(b"initiator to responder".as_slice()), r_rx.open("message", &mut message));
+
+        let mut message = b"responder to initiator".to_vec();
+        message.extend_from_slice(&[0u8; TAG_LEN]);
+        r_tx.seal("message", &mut message);
+        assert_eq!(Some(b"responder to initiator".as_slice()), i_rx.open("message", &mut message));
+
+        assert_ne!(i_tx.derive_array::<16>("check"), i_rx.derive_array::<16>("check"));
+    }
+}
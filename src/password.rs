@@ -0,0 +1,68 @@
+//! A password hash built on [`Protocol`].
+//!
+//! A password hash is computed by mixing the salt and password into a protocol, ratcheting it once
+//! per unit of work factor, and deriving a 256-bit hash:
+//!
+//! ```text
+//! function hash_password(password, salt, work_factor):
+//!   p ← init("lockstitch.password")
+//!   p ← mix(p, "work-factor", right_encode(work_factor))
+//!   p ← mix(p, "salt", salt)
+//!   p ← mix(p, "password", password)
+//!   repeat work_factor times:
+//!     (p, _) ← derive(p, "ratchet", 0)
+//!   (_, hash) ← derive(p, "hash", 256)
+//!   hash
+//! ```
+//!
+//! **IMPORTANT:** This is not a memory-hard password hash like Argon2 or scrypt. Its work factor is
+//! purely sequential computation, which is cheap to parallelize across guesses with dedicated
+//! hardware. Only use it where a fast, peppered or HMAC-style check is acceptable (e.g. verifying
+//! high-entropy secrets); use a memory-hard function for human-chosen passwords.
+
+use crate::{ct_eq, Protocol};
+
+/// Hashes the given password with the given salt and work factor.
+pub fn hash_password(password: &[u8], salt: &[u8], work_factor: u32) -> [u8; 32] {
+    let mut protocol = Protocol::new("lockstitch.password");
+    protocol.mix_int("work-factor", work_factor.into());
+    protocol.mix("salt", salt);
+    protocol.mix("password", password);
+    protocol.ratchet_n("ratchet", work_factor);
+    protocol.derive_array("hash")
+}
+
+/// Returns `true` if the given password, salt, and work factor produce the stored hash.
+///
+/// The hashes are compared in constant time.
+#[must_use]
+pub fn verify_password(password: &[u8], salt: &[u8], work_factor: u32, stored: &[u8]) -> bool {
+    ct_eq(stored, &hash_password(password, salt, work_factor))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn known_answers() {
+        expect!["bc196f094a0c84670f15453bf2dfd480ccea50a8f65b1ec32f83bd1da2ef7afd"]
+            .assert_eq(&hex::encode(hash_password(b"password", b"salt", 0)));
+        expect!["5473c843cd732cb8e5d7a2c00c52eb3543bafe9362d9a2b2f53419e6245527ec"]
+            .assert_eq(&hex::encode(hash_password(b"password", b"salt", 1)));
+        expect!["5aae6486b09db6c7bd480f72491515ad6b4afecb74ff7562390e5c0c701e0c46"]
+            .assert_eq(&hex::encode(hash_password(b"password", b"salt", 1000)));
+    }
+
+    #[test]
+    fn verify() {
+        let stored = hash_password(b"password", b"salt", 100);
+        assert!(verify_password(b"password", b"salt", 100, &stored));
+        assert!(!verify_password(b"passwore", b"salt", 100, &stored));
+        assert!(!verify_password(b"password", b"salu", 100, &stored));
+        assert!(!verify_password(b"password", b"salt", 99, &stored));
+        assert!(!verify_password(b"password", b"salt", 100, &stored[..16]));
+    }
+}
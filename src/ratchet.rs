@@ -0,0 +1,105 @@
+//! A symmetric-key ratchet built on [`Protocol`], e.g. for the sending and receiving chains of a
+//! Signal-style double ratchet.
+//!
+//! A [`SymmetricRatchet`] holds a protocol keyed with a chain key. Each call to
+//! [`SymmetricRatchet::next_key`] derives a message key with the label `message-key` and then
+//! ratchets the protocol with the label `chain`. Both operations replace the protocol's
+//! transcript with a KDK derived from it, so a compromise of the ratchet's state reveals message
+//! keys for future messages but not for any message before the compromise (forward secrecy).
+//! Message keys must be used once and then discarded; retaining them defeats forward secrecy.
+//!
+//! Both parties must start from the same protocol state and derive message keys in the same order.
+//! This module does not handle skipped or out-of-order messages, for which a receiver would store
+//! the message keys it skipped over.
+
+use crate::Protocol;
+
+/// The length of a message key in bytes.
+pub const MESSAGE_KEY_LEN: usize = 32;
+
+/// A symmetric-key ratchet which derives a sequence of message keys from a protocol.
+#[derive(Debug, Clone)]
+pub struct SymmetricRatchet {
+    protocol: Protocol,
+    index: u64,
+}
+
+impl SymmetricRatchet {
+    /// Creates a new ratchet from the given protocol, which should contain a secret chain key.
+    pub const fn new(protocol: Protocol) -> SymmetricRatchet {
+        SymmetricRatchet { protocol, index: 0 }
+    }
+
+    /// Returns the index of the next message key.
+    pub const fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Derives the next message key and advances the chain irreversibly, returning the message key
+    /// and its index.
+    pub fn next_key(&mut self) -> ([u8; MESSAGE_KEY_LEN], u64) {
+        let key = self.protocol.derive_array("message-key");
+        self.protocol.ratchet("chain");
+        let index = self.index;
+        self.index += 1;
+        (key, index)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    fn ratchet() -> SymmetricRatchet {
+        let mut protocol = Protocol::new("com.example.ratchet");
+        protocol.mix("chain-key", b"a shared chain key");
+        SymmetricRatchet::new(protocol)
+    }
+
+    #[test]
+    fn known_answers() {
+        let mut ratchet = ratchet();
+        let (key, index) = ratchet.next_key();
+        assert_eq!(0, index);
+        expect!["d65b9dce9939cb2e29c7b2c122daedeb983b5bc4347dbc7a6cea1106a7c4aa61"]
+            .assert_eq(&hex::encode(key));
+        assert_eq!(1, ratchet.next_key().1);
+        assert_eq!(2, ratchet.index());
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut protocol = Protocol::new("com.example.ratchet");
+        protocol.mix("chain-key", b"a shared chain key");
+
+        let mut ratchet = ratchet();
+        for _ in 0..3 {
+            let expected = protocol.derive_array::<MESSAGE_KEY_LEN>("message-key");
+            protocol.ratchet("chain");
+            assert_eq!(expected, ratchet.next_key().0);
+        }
+    }
+
+    #[test]
+    fn forward_secrecy() {
+        let mut sender = ratchet();
+        let mut receiver = ratchet();
+
+        let old_keys = (0..10).map(|_| sender.next_key().0).collect::<Vec<_>>();
+        for key in &old_keys {
+            assert_eq!(*key, receiver.next_key().0);
+        }
+
+        // A compromised ratchet only produces keys for later messages, none of which are equal to
+        // any earlier key.
+        let mut compromised = sender.clone();
+        for _ in 0..1_000 {
+            let (key, index) = compromised.next_key();
+            assert!(index >= 10);
+            assert!(!old_keys.contains(&key));
+        }
+        assert_eq!(sender.next_key(), receiver.next_key());
+    }
+}
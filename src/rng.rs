@@ -0,0 +1,111 @@
+//! A deterministic CSPRNG built on a protocol.
+
+use core::fmt;
+
+use rand_core::{impls, CryptoRng, Error, RngCore, SeedableRng};
+
+use crate::Protocol;
+
+/// A deterministic, cryptographically secure random number generator which produces output with
+/// [`Protocol::derive`].
+///
+/// Each call to [`RngCore::fill_bytes`] is a single `Derive` operation with the label `output`,
+/// which advances the protocol's state. The output for a given seed is a stable part of the
+/// construction, so a `ProtocolRng` is suitable for reproducible simulations and test fixtures. It
+/// is only as unpredictable as its seed, which must be secret and uniformly random for the output
+/// to be.
+#[derive(Clone)]
+pub struct ProtocolRng {
+    protocol: Protocol,
+}
+
+impl ProtocolRng {
+    /// Creates a new RNG keyed with the given domain separation string and seed.
+    #[inline]
+    pub fn new_keyed(domain: &str, seed: &[u8; 32]) -> ProtocolRng {
+        let mut protocol = Protocol::new(domain);
+        protocol.mix("seed", seed);
+        ProtocolRng { protocol }
+    }
+}
+
+impl fmt::Debug for ProtocolRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProtocolRng").finish_non_exhaustive()
+    }
+}
+
+impl RngCore for ProtocolRng {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.protocol.derive("output", dest);
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for ProtocolRng {}
+
+impl SeedableRng for ProtocolRng {
+    type Seed = [u8; 32];
+
+    /// Creates a new RNG with the domain separation string `lockstitch.rng` and the given seed.
+    #[inline]
+    fn from_seed(seed: Self::Seed) -> ProtocolRng {
+        ProtocolRng::new_keyed("lockstitch.rng", &seed)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use expect_test::expect;
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn known_answers() {
+        let mut rng = ProtocolRng::from_seed([7u8; 32]);
+        let mut out = [0u8; 16];
+        rng.fill_bytes(&mut out);
+        expect!["381f22696903af7f6b5dad951a5d6c32"].assert_eq(&hex::encode(out));
+        expect!["11852349959068118874"].assert_eq(&rng.next_u64().to_string());
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut rng = ProtocolRng::new_keyed("com.example.rng", &[7u8; 32]);
+        let mut protocol = Protocol::new("com.example.rng");
+        protocol.mix("seed", &[7u8; 32]);
+
+        let mut out = [0u8; 100];
+        rng.fill_bytes(&mut out);
+        assert_eq!(out, protocol.derive_array::<100>("output"));
+        assert_eq!(rng.next_u32().to_le_bytes(), protocol.derive_array::<4>("output"));
+    }
+
+    #[test]
+    fn reproducible() {
+        let a = ProtocolRng::seed_from_u64(22).gen_range(0..1_000_000);
+        let b = ProtocolRng::seed_from_u64(22).gen_range(0..1_000_000);
+        assert_eq!(a, b);
+        assert_ne!(
+            ProtocolRng::from_seed([1u8; 32]).gen::<u64>(),
+            ProtocolRng::from_seed([2u8; 32]).gen::<u64>()
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! A sealing API which requires a nonce for every message.
+//!
+//! A [`NonceSealer`] holds a keyed protocol. Each message is sealed or opened with a clone of that
+//! protocol, which first mixes the message's nonce with the label `nonce`, so messages are
+//! independent of each other and can be opened in any order. The nonce is a required argument,
+//! which makes it impossible to seal a message without one.
+//!
+//! Sealing two messages with the same nonce reveals the XOR of their plaintexts and allows
+//! forgeries. In debug builds with the `std` feature, a [`NonceSealer`] records every nonce it has
+//! sealed with and panics if one is reused. Release builds don't track nonces, so callers must
+//! still ensure uniqueness, e.g. with a counter or a random 16-byte value.
+//!
+//! The set of recorded nonces is never pruned, so a debug build's sealer uses memory proportional
+//! to the total length of the nonces it has sealed with.
+
+use crate::Protocol;
+
+/// A sealer which requires a unique nonce for every message.
+///
+/// It isn't [`Clone`], since two clones would each track their own nonces and could reuse each
+/// other's without panicking.
+#[derive(Debug)]
+pub struct NonceSealer {
+    protocol: Protocol,
+    /// Every nonce sealed with so far. Grows by one entry per sealed message and is never pruned.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    used: std::collections::HashSet<Vec<u8>>,
+}
+
+impl NonceSealer {
+    /// Creates a new sealer from the given protocol, which should contain a secret key.
+    // `HashSet::new` isn't const, so this can only be const when the nonce set is compiled out.
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn new(protocol: Protocol) -> NonceSealer {
+        NonceSealer {
+            protocol,
+            #[cfg(all(debug_assertions, feature = "std"))]
+            used: std::collections::HashSet::new(),
+        }
+    }
+
+    /// Seals the given mutable slice in place with the given nonce.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `nonce` and the nonce
+    /// on a clone of the sealer's protocol, followed by [`Protocol::seal`] with the given label.
+    /// The last [`crate::TAG_LEN`] bytes of the slice will be overwritten with the authentication
+    /// tag.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds with the `std` feature, panics if the nonce has been used before.
+    pub fn seal_with_nonce(&mut self, label: &str, nonce: &[u8], in_out: &mut [u8]) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        assert!(self.used.insert(nonce.to_vec()), "nonce reused");
+
+        let mut protocol = self.protocol.clone();
+        protocol.mix("nonce", nonce);
+        protocol.seal(label, in_out);
+    }
+
+    /// Opens the given mutable slice in place with the given nonce. Returns the plaintext if the
+    /// input was authenticated.
+    ///
+    /// This is exactly equivalent to calling [`Protocol::mix`] with the label `nonce` and the nonce
+    /// on a clone of the sealer's protocol, followed by [`Protocol::open`] with the given label.
+    #[must_use]
+    pub fn open_with_nonce<'ct>(
+        &self,
+        label: &str,
+        nonce: &[u8],
+        in_out: &'ct mut [u8],
+    ) -> Option<&'ct [u8]> {
+        let mut protocol = self.protocol.clone();
+        protocol.mix("nonce", nonce);
+        protocol.open(label, in_out)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::TAG_LEN;
+
+    use super::*;
+
+    fn sealer() -> NonceSealer {
+        let mut protocol = Protocol::new("com.example.sealer");
+        protocol.mix("key", b"a secret key");
+        NonceSealer::new(protocol)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut sender = sealer();
+        let receiver = sealer();
+
+        let mut first = *b"first message\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        sender.seal_with_nonce("message", &[1], &mut first);
+        let mut second = *b"second message\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        sender.seal_with_nonce("message", &[2], &mut second);
+
+        // Messages can be opened in any order, but only with their own nonces.
+        assert_eq!(
+            Some(b"second message".as_slice()),
+            receiver.open_with_nonce("message", &[2], &mut second.clone())
+        );
+        assert_eq!(
+            Some(b"first message".as_slice()),
+            receiver.open_with_nonce("message", &[1], &mut first.clone())
+        );
+        assert_eq!(None, receiver.open_with_nonce("message", &[2], &mut first));
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut expected = Protocol::new("com.example.sealer");
+        expected.mix("key", b"a secret key");
+        expected.mix("nonce", b"a nonce");
+        let mut expected_out = [0u8; 10 + TAG_LEN];
+        expected.seal("message", &mut expected_out);
+
+        let mut out = [0u8; 10 + TAG_LEN];
+        sealer().seal_with_nonce("message", b"a nonce", &mut out);
+        assert_eq!(expected_out, out);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "nonce reused")]
+    fn nonce_reuse() {
+        let mut sealer = sealer();
+        sealer.seal_with_nonce("message", b"a nonce", &mut [0u8; TAG_LEN]);
+        sealer.seal_with_nonce("message", b"a nonce", &mut [0u8; TAG_LEN]);
+    }
+}
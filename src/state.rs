@@ -0,0 +1,269 @@
+//! The serialized state of a [`Protocol`].
+
+use core::fmt;
+
+use crate::{ct_eq, OpCode, Protocol, KDK_LEN};
+
+/// The magic header of a serialized protocol state.
+const MAGIC: &[u8; 4] = b"LkSt";
+
+/// The version of the serialized protocol state format.
+const VERSION: u8 = 1;
+
+/// The length of a serialized protocol state in bytes.
+pub const STATE_LEN: usize = MAGIC.len() + 1 + KDK_LEN + 8;
+
+/// The serialized state of a [`Protocol`], as returned by [`Protocol::export_state`].
+///
+/// This is a distinct type from [`Protocol`] so that live protocols can't be serialized by
+/// accident. With the `serde` feature enabled, it implements `Serialize` and `Deserialize` as a
+/// [`STATE_LEN`]-byte string.
+///
+/// **IMPORTANT:** A protocol's state is as sensitive as any secret mixed into the protocol.
+#[derive(Clone)]
+pub struct ProtocolState {
+    kdk: [u8; KDK_LEN],
+    bytes_crypted: u64,
+}
+
+impl ProtocolState {
+    /// Encodes the state as a magic header and version, the KDK, and the big-endian value of
+    /// [`Protocol::bytes_crypted`].
+    pub fn to_bytes(&self) -> [u8; STATE_LEN] {
+        let mut out = [0u8; STATE_LEN];
+        let (magic, rest) = out.split_at_mut(MAGIC.len());
+        let (version, rest) = rest.split_at_mut(1);
+        let (kdk, bytes_crypted) = rest.split_at_mut(KDK_LEN);
+        magic.copy_from_slice(MAGIC);
+        version[0] = VERSION;
+        kdk.copy_from_slice(&self.kdk);
+        bytes_crypted.copy_from_slice(&self.bytes_crypted.to_be_bytes());
+        out
+    }
+
+    /// Decodes a state encoded with [`ProtocolState::to_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`StateError`] if `bytes` is the wrong length, has the wrong magic header, or has
+    /// an unsupported version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ProtocolState, StateError> {
+        if bytes.len() != STATE_LEN {
+            return Err(StateError::InvalidLength);
+        }
+
+        let (magic, rest) = bytes.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let (version, rest) = rest.split_at(1);
+        if version[0] != VERSION {
+            return Err(StateError::UnsupportedVersion(version[0]));
+        }
+
+        let (kdk, bytes_crypted) = rest.split_at(KDK_LEN);
+        Ok(ProtocolState {
+            kdk: kdk.try_into().expect("should be KDK_LEN bytes"),
+            bytes_crypted: u64::from_be_bytes(bytes_crypted.try_into().expect("should be 8 bytes")),
+        })
+    }
+}
+
+impl PartialEq for ProtocolState {
+    /// Compares two states in constant time.
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(&self.to_bytes(), &other.to_bytes())
+    }
+}
+
+impl Eq for ProtocolState {}
+
+impl fmt::Debug for ProtocolState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the KDK.
+        f.debug_struct("ProtocolState").finish_non_exhaustive()
+    }
+}
+
+/// An error returned when a serialized protocol state is malformed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StateError {
+    /// The state is not [`STATE_LEN`] bytes long.
+    InvalidLength,
+    /// The state does not begin with the expected magic header.
+    InvalidMagic,
+    /// The state has an unsupported format version.
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidLength => write!(f, "protocol state has an invalid length"),
+            StateError::InvalidMagic => write!(f, "not a lockstitch protocol state"),
+            StateError::UnsupportedVersion(v) => {
+                write!(f, "unsupported lockstitch protocol state version {v}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+impl Protocol {
+    /// Ratchets the protocol and returns its state.
+    ///
+    /// The protocol's transcript cannot be serialized directly, so this first performs a `Derive`
+    /// operation with the label `save` and no output, replacing the transcript with a KDK. The
+    /// state consists of the KDK and the value of [`Protocol::bytes_crypted`]. A protocol created
+    /// with [`Protocol::from_state`] is identical to this protocol after its state is exported.
+    #[inline]
+    pub fn export_state(&mut self) -> ProtocolState {
+        let (kdk, _) = self.derive_reader(OpCode::Derive, "save", 0);
+        ProtocolState { kdk, bytes_crypted: self.bytes_crypted }
+    }
+
+    /// Creates a protocol from a state returned by [`Protocol::export_state`].
+    #[inline]
+    pub fn from_state(state: &ProtocolState) -> Protocol {
+        // Begin a new transcript with a Mix operation using the KDK as input, exactly as a
+        // `Derive` operation does.
+        let mut protocol = Protocol::empty();
+        protocol.bytes_crypted = state.bytes_crypted;
+        protocol.mix("kdk", &state.kdk);
+        protocol
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ProtocolState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ProtocolState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StateVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for StateVisitor {
+            type Value = ProtocolState;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a {STATE_LEN}-byte lockstitch protocol state")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<ProtocolState, E> {
+                ProtocolState::from_bytes(v).map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<ProtocolState, A::Error> {
+                let mut bytes = [0u8; STATE_LEN];
+                for (i, b) in bytes.iter_mut().enumerate() {
+                    *b = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(serde::de::Error::custom(StateError::InvalidLength));
+                }
+                ProtocolState::from_bytes(&bytes).map_err(serde::de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_bytes(StateVisitor)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn protocol() -> Protocol {
+        let mut protocol = Protocol::new("com.example.state");
+        protocol.mix("key", b"a secret key");
+        protocol.encrypt("message", &mut [0u8; 10]);
+        protocol
+    }
+
+    #[test]
+    fn equality() {
+        let state = protocol().export_state();
+        assert_eq!(state, protocol().export_state());
+
+        let mut other = protocol();
+        other.mix("more", b"input");
+        assert_ne!(state, other.export_state());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut protocol = protocol();
+        let state = protocol.export_state();
+        assert_eq!(Ok(state.clone()), ProtocolState::from_bytes(&state.to_bytes()));
+
+        let mut restored = Protocol::from_state(&state);
+        assert_eq!(10, restored.bytes_crypted());
+        assert_eq!(protocol.derive_array::<16>("state"), restored.derive_array::<16>("state"));
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = protocol().export_state().to_bytes();
+
+        assert_eq!(Err(StateError::InvalidLength), ProtocolState::from_bytes(&bytes[1..]));
+        assert_eq!(
+            Err(StateError::InvalidLength),
+            ProtocolState::from_bytes(&[0u8; STATE_LEN + 1])
+        );
+
+        let mut wrong_magic = bytes;
+        wrong_magic[0] ^= 1;
+        assert_eq!(Err(StateError::InvalidMagic), ProtocolState::from_bytes(&wrong_magic));
+
+        let mut wrong_version = bytes;
+        wrong_version[4] = 2;
+        assert_eq!(
+            Err(StateError::UnsupportedVersion(2)),
+            ProtocolState::from_bytes(&wrong_version)
+        );
+    }
+
+    #[test]
+    fn debug_is_opaque() {
+        assert_eq!("ProtocolState { .. }", format!("{:?}", protocol().export_state()));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_json() {
+        let state = protocol().export_state();
+        let json = serde_json::to_string(&state).expect("should serialize");
+        assert_eq!(state, serde_json::from_str(&json).expect("should deserialize"));
+
+        assert!(serde_json::from_str::<ProtocolState>("[1,2,3]").is_err());
+        let mut long = serde_json::to_value(&state).expect("should serialize");
+        long.as_array_mut().expect("should be an array").push(0.into());
+        assert!(serde_json::from_value::<ProtocolState>(long).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn bincode() {
+        let state = protocol().export_state();
+        let bytes = bincode::serialize(&state).expect("should serialize");
+        assert_eq!(state, bincode::deserialize(&bytes).expect("should deserialize"));
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[8] ^= 1;
+        assert!(bincode::deserialize::<ProtocolState>(&wrong_magic).is_err());
+        assert!(bincode::deserialize::<ProtocolState>(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Canonical encodings of values for mixing into a protocol.
+
+use crate::Protocol;
+
+/// A type with a canonical encoding as a sequence of protocol operations.
+///
+/// Every implementation must only perform operations (e.g. [`Protocol::mix`]) which frame their
+/// inputs with lengths, so that the encoding of a value is unambiguous given its type. Values of
+/// different types may have the same encoding (e.g. `1u8` and `1u64`), so the types of transcribed
+/// values are part of a protocol's specification in the same way its labels are.
+pub trait Transcribe {
+    /// Mixes the value into the given protocol with the given label.
+    fn transcribe(&self, label: &str, protocol: &mut Protocol);
+}
+
+impl Protocol {
+    /// Mixes the given label and value into the protocol state using the value's [`Transcribe`]
+    /// encoding.
+    #[inline]
+    pub fn mix_transcribable<T: Transcribe + ?Sized>(&mut self, label: &str, value: &T) {
+        value.transcribe(label, self);
+    }
+}
+
+impl<T: Transcribe + ?Sized> Transcribe for &T {
+    #[inline]
+    fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+        (**self).transcribe(label, protocol);
+    }
+}
+
+impl Transcribe for [u8] {
+    #[inline]
+    fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+        protocol.mix(label, self);
+    }
+}
+
+impl<const N: usize> Transcribe for [u8; N] {
+    #[inline]
+    fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+        protocol.mix(label, self);
+    }
+}
+
+impl Transcribe for str {
+    #[inline]
+    fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+        protocol.mix_str(label, self);
+    }
+}
+
+macro_rules! transcribe_unsigned {
+    ($($t:ty),*) => {$(
+        /// Encoded with [`Protocol::mix_int`].
+        impl Transcribe for $t {
+            #[inline]
+            fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+                protocol.mix_int(label, *self as u64);
+            }
+        }
+    )*};
+}
+
+transcribe_unsigned!(u8, u16, u32, u64, usize);
+
+macro_rules! transcribe_big_endian {
+    ($($t:ty),*) => {$(
+        /// Encoded as a big-endian two's complement value.
+        impl Transcribe for $t {
+            #[inline]
+            fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+                protocol.mix(label, &self.to_be_bytes());
+            }
+        }
+    )*};
+}
+
+transcribe_big_endian!(u128, i8, i16, i32, i64, i128);
+
+macro_rules! transcribe_tuple {
+    ($($name:ident),+) => {
+        /// Each element is transcribed in order with the same label.
+        impl<$($name: Transcribe),+> Transcribe for ($($name,)+) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn transcribe(&self, label: &str, protocol: &mut Protocol) {
+                let ($($name,)+) = self;
+                $($name.transcribe(label, protocol);)+
+            }
+        }
+    };
+}
+
+transcribe_tuple!(A);
+transcribe_tuple!(A, B);
+transcribe_tuple!(A, B, C);
+transcribe_tuple!(A, B, C, D);
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn transcribe<T: Transcribe + ?Sized>(value: &T) -> [u8; 16] {
+        let mut protocol = Protocol::new("com.example.transcribe");
+        protocol.mix_transcribable("value", value);
+        protocol.derive_array("state")
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut protocol = Protocol::new("com.example.transcribe");
+        protocol.mix_str("value", "name");
+        protocol.mix_int("value", 22);
+        protocol.mix("value", &(-1i32).to_be_bytes());
+        assert_eq!(protocol.derive_array::<16>("state"), transcribe(&("name", 22u64, -1i32)));
+    }
+
+    #[test]
+    fn distinct_structures() {
+        let outputs = [
+            transcribe(&("ab", "c")),
+            transcribe(&("a", "bc")),
+            transcribe(&("abc",)),
+            transcribe(&("abc", "")),
+            transcribe(&("", "abc")),
+            transcribe(&(b"ab".as_slice(), 1u8)),
+            transcribe(&(b"ab".as_slice(), 1u8, 0u8)),
+            transcribe(&(1u64, 2u64)),
+            transcribe(&(2u64, 1u64)),
+        ];
+
+        for (i, a) in outputs.iter().enumerate() {
+            for b in &outputs[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn distinct_integers() {
+        bolero::check!().with_type::<(u64, u64)>().for_each(|&(a, b)| {
+            if a != b {
+                assert_ne!(transcribe(&a), transcribe(&b));
+            }
+        });
+    }
+}
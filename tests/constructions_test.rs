@@ -94,6 +94,28 @@ fn stream_cipher() {
         });
 }
 
+#[test]
+fn crypt_symmetry() {
+    bolero::check!().with_type::<(String, Vec<u8>, Vec<u8>)>().for_each(|(domain, secret, m)| {
+        let mut sender = Protocol::new(domain);
+        sender.mix("secret", secret);
+        let mut c = m.clone();
+        sender.encrypt("message", &mut c);
+
+        let mut receiver = Protocol::new(domain);
+        receiver.mix("secret", secret);
+        let mut p = c.clone();
+        receiver.decrypt("message", &mut p);
+
+        assert_eq!(m, &p, "decryption should recover the plaintext");
+        assert_eq!(
+            sender.derive_array::<TAG_LEN>("tag"),
+            receiver.derive_array::<TAG_LEN>("tag"),
+            "sender and receiver should reach the same state"
+        );
+    });
+}
+
 #[test]
 fn aead() {
     fn ae_enc(domain: &str, k: &[u8], n: &[u8], d: &[u8], p: &[u8]) -> Vec<u8> {
@@ -0,0 +1,109 @@
+use lockstitch::{Protocol, TAG_LEN};
+
+const MESSAGES: &[&[u8]] =
+    &[b"hello", b"", b"a somewhat longer message which spans multiple AEGIS-128L blocks"];
+
+/// A party's transport protocols after a handshake: one for the messages it sends and one for the
+/// messages it receives.
+struct Transport {
+    send: Protocol,
+    recv: Protocol,
+}
+
+/// Simulates one party's side of a handshake in which both parties exchange ephemeral public keys
+/// and mix them and a shared secret into their transcripts, then split into a protocol for each
+/// direction.
+fn party(
+    own_pk: &[u8],
+    peer_pk: &[u8],
+    is_initiator: bool,
+    shared_secret: &[u8],
+) -> (Protocol, Protocol) {
+    let (initiator_pk, responder_pk) =
+        if is_initiator { (own_pk, peer_pk) } else { (peer_pk, own_pk) };
+
+    let mut protocol = Protocol::new("com.example.handshake");
+    protocol.mix("initiator-ephemeral", initiator_pk);
+    protocol.mix("responder-ephemeral", responder_pk);
+    protocol.mix_dh("ee", initiator_pk, responder_pk, shared_secret);
+
+    // Split into a protocol for messages sent by the initiator and one for messages sent by the
+    // responder.
+    let mut initiator = protocol.clone();
+    let mut responder = protocol;
+    initiator.mix("split", b"initiator");
+    responder.mix("split", b"responder");
+    (initiator, responder)
+}
+
+/// Simulates a handshake, returning the initiator's and the responder's transport protocols.
+fn handshake(shared_secret: &[u8]) -> (Transport, Transport) {
+    let initiator_pk = b"initiator ephemeral public key";
+    let responder_pk = b"responder ephemeral public key";
+
+    let (i_send, i_recv) = party(initiator_pk, responder_pk, true, shared_secret);
+    let (r_recv, r_send) = party(responder_pk, initiator_pk, false, shared_secret);
+
+    (Transport { send: i_send, recv: i_recv }, Transport { send: r_send, recv: r_recv })
+}
+
+fn seal(protocol: &mut Protocol, message: &[u8]) -> Vec<u8> {
+    let mut out = message.to_vec();
+    out.extend_from_slice(&[0u8; TAG_LEN]);
+    protocol.seal("message", &mut out);
+    out
+}
+
+#[test]
+fn transport_round_trip() {
+    let (mut initiator, mut responder) = handshake(b"a shared secret");
+
+    for &message in MESSAGES {
+        let mut sealed = seal(&mut initiator.send, message);
+        assert_eq!(Some(message), responder.recv.open("message", &mut sealed));
+
+        let mut sealed = seal(&mut responder.send, message);
+        assert_eq!(Some(message), initiator.recv.open("message", &mut sealed));
+    }
+}
+
+#[test]
+fn transport_tampering() {
+    let (mut initiator, mut responder) = handshake(b"a shared secret");
+
+    for &message in MESSAGES {
+        let sealed = seal(&mut initiator.send, message);
+
+        for i in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert_eq!(
+                None,
+                responder.recv.clone().open("message", &mut tampered),
+                "tampering with byte {i} should be detected"
+            );
+        }
+
+        // The responder stays in sync by opening the untampered message.
+        assert_eq!(Some(message), responder.recv.open("message", &mut sealed.clone()));
+    }
+}
+
+#[test]
+fn mismatched_handshakes() {
+    let (mut initiator, _) = handshake(b"a shared secret");
+    let (_, mut responder) = handshake(b"another shared secret");
+
+    let mut sealed = seal(&mut initiator.send, b"hello");
+    assert_eq!(None, responder.recv.open("message", &mut sealed));
+}
+
+#[test]
+fn directions_are_independent() {
+    let (mut initiator, mut responder) = handshake(b"a shared secret");
+
+    // A message sent by the initiator can't be reflected back to it.
+    let mut sealed = seal(&mut initiator.send, b"hello");
+    assert_eq!(None, initiator.recv.open("message", &mut sealed.clone()));
+    assert_eq!(Some(b"hello".as_slice()), responder.recv.open("message", &mut sealed));
+}
@@ -0,0 +1,71 @@
+use lockstitch::{Protocol, TAG_LEN};
+
+fn log() -> Protocol {
+    let mut log = Protocol::new("com.example.log");
+    log.mix("key", b"a log signing key");
+    log
+}
+
+/// Seals each entry with a copy of the log protocol, linked to the previous entry's tag.
+fn append_all(entries: &[String]) -> Vec<Vec<u8>> {
+    let mut prev_tag = [0u8; TAG_LEN];
+    entries
+        .iter()
+        .map(|entry| {
+            let mut sealed = entry.as_bytes().to_vec();
+            sealed.extend_from_slice(&[0u8; TAG_LEN]);
+            prev_tag = log().seal_linked("entry", &prev_tag, &mut sealed);
+            sealed
+        })
+        .collect()
+}
+
+/// Opens each entry, linking it to the tag of the last verified entry.
+fn verify_all(sealed: &[Vec<u8>]) -> Vec<Option<String>> {
+    let mut prev_tag = [0u8; TAG_LEN];
+    sealed
+        .iter()
+        .map(|sealed| {
+            let tag: [u8; TAG_LEN] = sealed[sealed.len() - TAG_LEN..].try_into().ok()?;
+            let mut sealed = sealed.clone();
+            let entry = log().open_linked("entry", &prev_tag, &mut sealed)?;
+            prev_tag = tag;
+            Some(String::from_utf8(entry.to_vec()).expect("should be UTF-8"))
+        })
+        .collect()
+}
+
+#[test]
+fn chain_verifies() {
+    let entries = (0..100).map(|i| format!("entry {i}")).collect::<Vec<_>>();
+    let sealed = append_all(&entries);
+
+    let verified = verify_all(&sealed);
+    assert!(verified.iter().zip(&entries).all(|(v, e)| v.as_ref() == Some(e)));
+}
+
+#[test]
+fn removing_an_entry_breaks_the_chain() {
+    let entries = (0..100).map(|i| format!("entry {i}")).collect::<Vec<_>>();
+    let mut sealed = append_all(&entries);
+    sealed.remove(50);
+
+    let verified = verify_all(&sealed);
+    assert!(verified[..50].iter().zip(&entries).all(|(v, e)| v.as_ref() == Some(e)));
+    assert!(verified[50..].iter().all(Option::is_none));
+}
+
+#[test]
+fn reordering_entries_breaks_the_chain() {
+    let entries = (0..100).map(|i| format!("entry {i}")).collect::<Vec<_>>();
+    let mut sealed = append_all(&entries);
+    sealed.swap(10, 11);
+
+    // Entry 11 fails to open in entry 10's place. Entry 10 still links to the last verified entry,
+    // entry 9, so it opens, but entry 12 then fails, as do all later entries.
+    let verified = verify_all(&sealed);
+    assert!(verified[..10].iter().all(Option::is_some));
+    assert_eq!(verified[10], None);
+    assert_eq!(verified[11].as_deref(), Some("entry 10"));
+    assert!(verified[12..].iter().all(Option::is_none));
+}
@@ -0,0 +1,51 @@
+use lockstitch::{Protocol, TAG_LEN};
+
+fn session() -> Protocol {
+    let mut session = Protocol::new("com.example.replay");
+    session.mix("key", b"a shared secret key");
+    session
+}
+
+fn seal(counter: u64, message: &[u8]) -> Vec<u8> {
+    let mut protocol = session();
+    protocol.expect_counter(counter);
+    let mut sealed = message.to_vec();
+    sealed.extend_from_slice(&[0u8; TAG_LEN]);
+    protocol.seal("message", &mut sealed);
+    sealed
+}
+
+struct Receiver {
+    next: u64,
+}
+
+impl Receiver {
+    fn open(&mut self, mut sealed: Vec<u8>) -> Option<Vec<u8>> {
+        let mut protocol = session();
+        protocol.expect_counter(self.next);
+        let plaintext = protocol.open("message", &mut sealed)?.to_vec();
+        self.next += 1;
+        Some(plaintext)
+    }
+}
+
+#[test]
+fn replays_fail_to_open() {
+    let first = seal(0, b"first");
+    let second = seal(1, b"second");
+
+    let mut receiver = Receiver { next: 0 };
+    assert_eq!(Some(b"first".to_vec()), receiver.open(first.clone()));
+
+    // Replaying the first message fails, since the receiver expects the second.
+    assert_eq!(None, receiver.open(first));
+
+    assert_eq!(Some(b"second".to_vec()), receiver.open(second.clone()));
+    assert_eq!(None, receiver.open(second));
+}
+
+#[test]
+fn skipped_messages_fail_to_open() {
+    let mut receiver = Receiver { next: 0 };
+    assert_eq!(None, receiver.open(seal(1, b"second")));
+}
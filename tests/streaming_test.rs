@@ -0,0 +1,37 @@
+use std::io::{self, Write};
+
+use lockstitch::{Protocol, TAG_LEN};
+
+const AD_LEN: usize = 8 * 1024 * 1024;
+
+/// Streams multiple megabytes of deterministic associated data into a new protocol.
+fn streamed_ad(domain: &str) -> Protocol {
+    let mut source = Protocol::new("com.example.ad-source");
+    let mut block = [0u8; 64 * 1024];
+
+    let mut writer = Protocol::new(domain).mix_writer("ad", io::sink());
+    for _ in 0..AD_LEN / block.len() {
+        source.derive("block", &mut block);
+        writer.write_all(&block).expect("mix writes should be infallible");
+    }
+    writer.into_inner().0
+}
+
+#[test]
+fn seal_after_streamed_ad() {
+    let mut sender = streamed_ad("com.example.archive");
+    sender.mix("key", b"a secret key");
+    let mut message = b"a small body".to_vec();
+    message.extend_from_slice(&[0u8; TAG_LEN]);
+    sender.seal("body", &mut message);
+
+    let mut receiver = streamed_ad("com.example.archive");
+    receiver.mix("key", b"a secret key");
+    let mut opened = message.clone();
+    assert_eq!(Some(b"a small body".as_slice()), receiver.open("body", &mut opened));
+
+    let mut other = Protocol::new("com.example.archive");
+    other.mix("ad", b"some other associated data");
+    other.mix("key", b"a secret key");
+    assert_eq!(None, other.open("body", &mut message));
+}
@@ -0,0 +1,55 @@
+use lockstitch::{Protocol, TAG_LEN};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const OPERATIONS: usize = 100_000;
+
+#[test]
+fn long_scripts_stay_in_sync() {
+    let mut rng = StdRng::seed_from_u64(0x6c6f636b);
+    let mut sender = Protocol::new("com.example.stress");
+    let mut receiver = Protocol::new("com.example.stress");
+
+    for i in 0..OPERATIONS {
+        let len = rng.gen_range(0..=128);
+        match rng.gen_range(0..5) {
+            0 => {
+                let mut input = vec![0u8; len];
+                rng.fill(input.as_mut_slice());
+                sender.mix("input", &input);
+                receiver.mix("input", &input);
+            }
+            1 => {
+                let mut a = vec![0u8; len];
+                let mut b = vec![0u8; len];
+                sender.derive("output", &mut a);
+                receiver.derive("output", &mut b);
+                assert_eq!(a, b, "derived outputs diverged at operation {i}");
+            }
+            2 => {
+                let mut plaintext = vec![0u8; len];
+                rng.fill(plaintext.as_mut_slice());
+                let mut ciphertext = plaintext.clone();
+                sender.encrypt("message", &mut ciphertext);
+                receiver.decrypt("message", &mut ciphertext);
+                assert_eq!(plaintext, ciphertext, "decryption failed at operation {i}");
+            }
+            3 => {
+                let mut plaintext = vec![0u8; len + TAG_LEN];
+                rng.fill(&mut plaintext[..len]);
+                let mut sealed = plaintext.clone();
+                sender.seal("message", &mut sealed);
+                assert_eq!(
+                    Some(&plaintext[..len]),
+                    receiver.open("message", &mut sealed),
+                    "open failed at operation {i}"
+                );
+            }
+            _ => {
+                sender.ratchet("ratchet");
+                receiver.ratchet("ratchet");
+            }
+        }
+    }
+
+    assert_eq!(sender.derive_array::<32>("final"), receiver.derive_array::<32>("final"));
+}
@@ -25,6 +25,13 @@ enum Command {
         args: Vec<String>,
     },
 
+    /// Compare core operation throughput and library size with and without the `std` feature.
+    BenchFeatures {
+        /// The maximum allowed slowdown of any `no_std` operation versus `std`, in percent.
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+    },
+
     /// Spin up stuff on GCE for perf testings.
     Cloud {
         #[clap(subcommand)]
@@ -57,6 +64,7 @@ fn main() -> Result<()> {
     match xtask.cmd.unwrap_or(Command::CI) {
         Command::CI => ci(&sh),
         Command::Bench { args } => bench(&sh, args),
+        Command::BenchFeatures { threshold } => bench_features(&sh, threshold),
         Command::Cloud { cmd } => match cmd {
             CloudCommand::Create => cloud_create(&sh),
             CloudCommand::Setup => cloud_setup(&sh),
@@ -96,6 +104,70 @@ fn bench(sh: &Shell, args: Vec<String>) -> Result<()> {
     Ok(())
 }
 
+fn bench_features(sh: &Shell, threshold: f64) -> Result<()> {
+    let std = bench_config(sh, &[])?;
+    let no_std = bench_config(sh, &["--no-default-features"])?;
+
+    println!("{:<10} {:>14} {:>14} {:>9}", "", "std", "no_std", "change");
+    println!(
+        "{:<10} {:>12} B {:>12} B {:>8.1}%",
+        "size",
+        std.size,
+        no_std.size,
+        change(std.size as f64, no_std.size as f64)
+    );
+
+    let mut regressions = Vec::new();
+    for (op, std_ns) in &std.times {
+        let Some((_, no_std_ns)) = no_std.times.iter().find(|(name, _)| name == op) else {
+            bail!("no_std benchmark output is missing {op}");
+        };
+        let change = change(*std_ns, *no_std_ns);
+        println!("{op:<10} {std_ns:>11.1} ns {no_std_ns:>11.1} ns {change:>8.1}%");
+        if change > threshold {
+            regressions.push(op.clone());
+        }
+    }
+
+    if !regressions.is_empty() {
+        bail!("no_std is more than {threshold}% slower than std for: {}", regressions.join(", "));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct BenchResults {
+    size: u64,
+    times: Vec<(String, f64)>,
+}
+
+fn bench_config(sh: &Shell, flags: &[&str]) -> Result<BenchResults> {
+    cmd!(sh, "cargo build --release -p lockstitch --lib {flags...}")
+        .env("RUSTFLAGS", RUSTFLAGS)
+        .run()?;
+    let size = std::fs::metadata(project_root().join("target/release/liblockstitch.rlib"))?.len();
+
+    let output = cmd!(sh, "cargo bench -p benchmarks --bench features {flags...}")
+        .env("RUSTFLAGS", RUSTFLAGS)
+        .read()?;
+    let times = output
+        .lines()
+        .map(|line| {
+            let Some((op, ns)) = line.split_once(' ') else {
+                bail!("invalid benchmark output: {line}");
+            };
+            Ok((op.to_owned(), ns.parse()?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(BenchResults { size, times })
+}
+
+fn change(base: f64, value: f64) -> f64 {
+    (value - base) / base * 100.0
+}
+
 fn cloud_create(sh: &Shell) -> Result<()> {
     cmd!(sh, "gcloud compute instances create lockstitch --zone=us-central1-a --machine-type=c3-standard-4 --min-cpu-platform 'Intel Sapphire Rapids' --image-project 'debian-cloud' --image-family 'debian-11'").run()?;
 